@@ -12,3 +12,17 @@ pub async fn extablish_mongodb_connection() -> Client {
         .expect("Failed to pass database url");
     Client::with_options(options).expect("Failed to create Mongodb client")
 }
+
+/// Connects to the MongoDB used by the ignored integration tests, read from
+/// `TEST_DATABASE_URL`. It must be a replica set for transactions to work.
+#[cfg(test)]
+pub async fn test_mongodb_connection() -> Client {
+    dotenv().ok();
+    let database_url = env::var("TEST_DATABASE_URL")
+        .unwrap_or_else(|_| String::from("mongodb://localhost:27017/?replicaSet=rs0"));
+
+    let options = ClientOptions::parse(&database_url)
+        .await
+        .expect("Failed to pass test database url");
+    Client::with_options(options).expect("Failed to create Mongodb client")
+}
@@ -0,0 +1,24 @@
+use std::env;
+
+use chrono::Duration;
+use dotenvy::dotenv;
+
+const DEFAULT_DEVICE_OFFLINE_AFTER_SECS: i64 = 300;
+
+/// How long a device may stay silent before it is reported offline, read from
+/// `DEVICE_OFFLINE_AFTER_SECS`.
+pub fn device_offline_after() -> Duration {
+    dotenv().ok();
+    let seconds = env::var("DEVICE_OFFLINE_AFTER_SECS")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_DEVICE_OFFLINE_AFTER_SECS);
+    Duration::seconds(seconds)
+}
+
+/// The public base URL devices should talk to, read from `API_BASE_URL`.
+pub fn api_base_url() -> String {
+    dotenv().ok();
+    env::var("API_BASE_URL").unwrap_or_else(|_| String::from("http://localhost:3000"))
+}
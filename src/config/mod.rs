@@ -1 +1,3 @@
 pub mod database;
+pub mod device;
+pub mod setup;
@@ -0,0 +1,12 @@
+use std::env;
+
+use dotenvy::dotenv;
+
+/// The one-time token that allows the first super-admin to be created, read
+/// from `SETUP_TOKEN`. Bootstrapping is disabled when it isn't set.
+pub fn setup_token() -> Option<String> {
+    dotenv().ok();
+    env::var("SETUP_TOKEN")
+        .ok()
+        .filter(|setup_token| !setup_token.is_empty())
+}
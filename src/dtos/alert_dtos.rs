@@ -0,0 +1,105 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::alert::{Alert, AlertComment, AlertMetric, AlertSeverity, AlertState};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertCommentDto {
+    pub author_id: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+impl From<AlertComment> for AlertCommentDto {
+    fn from(comment: AlertComment) -> Self {
+        AlertCommentDto {
+            author_id: comment.author_id,
+            body: comment.body,
+            created_at: comment.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertDto {
+    pub id: String,
+    pub cage_id: String,
+    pub assigned_monitor: String,
+    pub metric: AlertMetric,
+    pub severity: AlertSeverity,
+    pub state: AlertState,
+    pub active: bool,
+    pub threshold: f32,
+    pub trigger_value: f32,
+    pub last_value: f32,
+    pub opened_at: String,
+    pub cleared_at: Option<String>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<String>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub comments: Vec<AlertCommentDto>,
+    pub updated_at: String,
+}
+
+impl From<Alert> for AlertDto {
+    fn from(alert: Alert) -> Self {
+        AlertDto {
+            id: alert.id.to_string(),
+            cage_id: alert.cage_id,
+            assigned_monitor: alert.assigned_monitor,
+            metric: alert.metric,
+            severity: alert.severity,
+            state: alert.state,
+            active: alert.active,
+            threshold: alert.threshold,
+            trigger_value: alert.trigger_value,
+            last_value: alert.last_value,
+            opened_at: alert.opened_at.to_rfc3339(),
+            cleared_at: alert.cleared_at.map(|cleared_at| cleared_at.to_rfc3339()),
+            acknowledged_by: alert.acknowledged_by,
+            acknowledged_at: alert.acknowledged_at.map(|at| at.to_rfc3339()),
+            resolved_by: alert.resolved_by,
+            resolved_at: alert.resolved_at.map(|at| at.to_rfc3339()),
+            comments: alert
+                .comments
+                .into_iter()
+                .map(AlertCommentDto::from)
+                .collect(),
+            updated_at: alert.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct AlertQuery {
+    pub cage_id: Option<String>,
+    pub state: Option<String>,
+    pub severity: Option<String>,
+    pub offset: u64,
+    #[validate(range(min = 1, max = 100, message = "limit must be between 1 and 100"))]
+    pub limit: u64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct AddAlertCommentDto {
+    #[validate(length(min = 1, max = 2000, message = "comment must be 1-2000 characters"))]
+    pub body: String,
+}
+
+impl AddAlertCommentDto {
+    pub fn to_model(self, author_id: String) -> AlertComment {
+        AlertComment {
+            author_id,
+            body: self.body,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct UserAlertsResponse {
+    pub total_alerts: u64,
+    pub alerts: Vec<AlertDto>,
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::invitation::{Invitation, InvitationStatus};
+
+#[derive(Deserialize, Validate)]
+pub struct AcceptInvitationDto {
+    #[validate(length(min = 1, message = "token is required"))]
+    pub token: String,
+    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct InvitationDto {
+    pub id: String,
+    pub user_id: String,
+    pub email: String,
+    pub status: InvitationStatus,
+    pub expires_at: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl InvitationDto {
+    pub fn from_invitation(invitation: Invitation, status: InvitationStatus) -> Self {
+        InvitationDto {
+            id: invitation.id.to_string(),
+            user_id: invitation.user_id,
+            email: invitation.email,
+            status,
+            expires_at: invitation.expires_at.to_rfc3339(),
+            created_at: invitation.created_at.to_rfc3339(),
+            updated_at: invitation.updated_at.to_rfc3339(),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+pub mod alert_dtos;
 pub mod auth_dto;
+pub mod invitation_dtos;
+pub mod notification_dtos;
+pub mod plan_dtos;
 pub mod spm_dtos;
 pub mod user;
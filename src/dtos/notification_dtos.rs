@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::notification::{ChannelKind, NotificationChannel};
+
+#[derive(Deserialize, Validate)]
+pub struct CreateNotificationChannelDto {
+    #[validate(length(min = 1, message = "kind is required"))]
+    pub kind: String,
+    #[validate(length(min = 1, message = "address is required"))]
+    pub address: String,
+    #[validate(length(min = 16, message = "secret must be at least 16 characters"))]
+    pub secret: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct NotificationChannelDto {
+    pub id: String,
+    pub kind: ChannelKind,
+    pub address: String,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<NotificationChannel> for NotificationChannelDto {
+    fn from(channel: NotificationChannel) -> Self {
+        NotificationChannelDto {
+            id: channel.id.to_string(),
+            kind: channel.kind,
+            address: channel.address,
+            enabled: channel.enabled,
+            secret: None,
+            created_at: channel.created_at.to_rfc3339(),
+            updated_at: channel.updated_at.to_rfc3339(),
+        }
+    }
+}
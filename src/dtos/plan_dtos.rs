@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::plan::{ExportFormat, Plan};
+
+#[derive(Deserialize, Validate)]
+pub struct UpdatePlanDto {
+    #[validate(length(min = 1, message = "name is required"))]
+    pub name: String,
+    #[validate(range(min = 1, message = "max_customers must be at least 1"))]
+    pub max_customers: u32,
+    pub max_cages: u32,
+    #[validate(range(
+        min = 1,
+        max = 3650,
+        message = "retention must be between 1 day and 10 years"
+    ))]
+    pub retention_days: u32,
+    pub export_formats: Vec<ExportFormat>,
+}
+
+#[derive(Serialize)]
+pub struct PlanDto {
+    pub admin_id: String,
+    pub name: String,
+    pub max_customers: u32,
+    pub max_cages: u32,
+    pub retention_days: u32,
+    pub export_formats: Vec<ExportFormat>,
+    pub updated_at: String,
+}
+
+impl From<Plan> for PlanDto {
+    fn from(plan: Plan) -> Self {
+        PlanDto {
+            admin_id: plan.admin_id.to_string(),
+            name: plan.name,
+            max_customers: plan.max_customers,
+            max_cages: plan.max_cages,
+            retention_days: plan.retention_days,
+            export_formats: plan.export_formats,
+            updated_at: plan.updated_at.to_rfc3339(),
+        }
+    }
+}
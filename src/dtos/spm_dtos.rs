@@ -1,13 +1,17 @@
 use std::{fmt, str::FromStr};
 
 use bson::oid::ObjectId;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use crate::models::spm::{Cage, HealthSettings, ObjectRecognition};
+use crate::models::spm::{
+    Cage, CageReading, CageWithDeviceToken, DeviceTokenVersion, DiseaseRiskBucket,
+    DiseaseThreshold, HealthSettings, MetricStats, ObjectRecognition, ReadingBucket, SensorRange,
+    SpmDevice,
+};
 
-#[derive(Deserialize, Validate)]
+#[derive(Serialize, Deserialize, Validate)]
 pub struct AddNewCageDto {
     #[validate(length(min = 1, message = "cageID is required"))]
     pub cage_id: String,
@@ -23,19 +27,8 @@ impl AddNewCageDto {
             id: ObjectId::new(),
             cage_id: self.cage_id,
             livestock_no: self.livestock_no,
+            archived_at: None,
             assigned_monitor: self.assigned_monitor,
-            co2: 0.0,
-            ammonia: 0.0,
-            humidity: 0.0,
-            pressure: 0.0,
-            temperature: 38.6,
-            object_recognition: ObjectRecognition {
-                coccidiosis: 0.0,
-                newcastle: 0.0,
-                salmonella: 0.0,
-                healthy: 0.0,
-            },
-            timestamp: Utc::now(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -43,6 +36,81 @@ impl AddNewCageDto {
 }
 
 #[derive(Deserialize, Validate)]
+pub struct BulkAddCagesDto {
+    #[validate(
+        length(min = 1, max = 100, message = "between 1 and 100 cages are required"),
+        nested
+    )]
+    pub cages: Vec<AddNewCageDto>,
+}
+
+#[derive(Serialize)]
+pub struct BulkAddCagesResponse {
+    pub cages: Vec<CageWithDeviceToken>,
+    /// Downloadable once from `/spm/provisioning-sheets/:sheet_id`.
+    pub provisioning_sheet_id: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct EditCageDto {
+    pub livestock_no: u32,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ReassignCageDto {
+    #[validate(length(min = 1, message = "assigned_monitor is required"))]
+    pub assigned_monitor: String,
+}
+
+#[derive(Serialize)]
+pub struct CageInfoDto {
+    pub id: String,
+    pub cage_id: String,
+    pub assigned_monitor: String,
+    pub livestock_no: u32,
+    pub archived: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<Cage> for CageInfoDto {
+    fn from(cage: Cage) -> Self {
+        CageInfoDto {
+            id: cage.id.to_string(),
+            cage_id: cage.cage_id,
+            assigned_monitor: cage.assigned_monitor,
+            livestock_no: cage.livestock_no,
+            archived: cage.archived_at.is_some(),
+            archived_at: cage.archived_at.map(|archived_at| archived_at.to_rfc3339()),
+            created_at: cage.created_at.to_rfc3339(),
+            updated_at: cage.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CageImportResult {
+    /// 1-based line number in the CSV, counting the header.
+    pub line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cage_id: Option<String>,
+    pub created: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_token: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportCagesResponse {
+    pub created: usize,
+    pub rejected: usize,
+    pub results: Vec<CageImportResult>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
 pub struct UpdateCageDto {
     pub temperature: f32,
     pub humidity: f32,
@@ -53,13 +121,59 @@ pub struct UpdateCageDto {
     pub timestamp: DateTime<Utc>,
 }
 
+/// A buffered batch of readings, sent as a bare JSON array. Each reading is
+/// checked on its own so one bad reading doesn't reject the rest.
+#[derive(Deserialize, Validate)]
+#[serde(transparent)]
+pub struct BatchUpdateCageDto {
+    #[validate(length(
+        min = 1,
+        max = 1000,
+        message = "A batch must contain between 1 and 1000 readings"
+    ))]
+    pub readings: Vec<UpdateCageDto>,
+}
+
 impl UpdateCageDto {
-    pub fn to_model(self, cage_id: String, livestock_no: u32, assigned_monitor: String) -> Cage {
-        Cage {
+    pub fn check_reading(&self) -> Result<(), String> {
+        let values = [
+            self.temperature,
+            self.humidity,
+            self.pressure,
+            self.ammonia,
+            self.co2,
+        ];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(String::from("sensor values must be finite numbers"));
+        }
+
+        let recognition = &self.object_recognition;
+        let probabilities = [
+            recognition.coccidiosis,
+            recognition.newcastle,
+            recognition.salmonella,
+            recognition.healthy,
+        ];
+        if probabilities
+            .iter()
+            .any(|probability| !(0.0..=1.0).contains(probability))
+        {
+            return Err(String::from(
+                "object recognition scores must be between 0 and 1",
+            ));
+        }
+
+        if self.timestamp > Utc::now() + Duration::minutes(5) {
+            return Err(String::from("timestamp is in the future"));
+        }
+
+        Ok(())
+    }
+
+    pub fn to_model(self, cage_id: String) -> CageReading {
+        CageReading {
             id: ObjectId::new(),
             cage_id,
-            livestock_no,
-            assigned_monitor,
             co2: self.co2,
             ammonia: self.ammonia,
             humidity: self.humidity,
@@ -68,12 +182,26 @@ impl UpdateCageDto {
             object_recognition: self.object_recognition,
             timestamp: self.timestamp,
             created_at: Utc::now(),
-            updated_at: Utc::now(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Serialize)]
+pub struct BatchReadingResult {
+    pub index: usize,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchUpdateCageResponse {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub results: Vec<BatchReadingResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CageDto {
     pub id: String,
     pub cage_id: String,
@@ -87,29 +215,114 @@ pub struct CageDto {
     pub object_recognition: ObjectRecognition,
     pub timestamp: String,
     pub created_at: String,
-    pub updated_at: String,
 }
 
-impl From<Cage> for CageDto {
-    fn from(cage: Cage) -> Self {
+impl CageDto {
+    pub fn from_reading(cage: &Cage, reading: CageReading) -> Self {
         CageDto {
-            id: cage.id.to_string(),
-            cage_id: cage.cage_id,
-            assigned_monitor: cage.assigned_monitor,
+            id: reading.id.to_string(),
+            cage_id: reading.cage_id,
+            assigned_monitor: cage.assigned_monitor.clone(),
             livestock_no: cage.livestock_no,
-            temperature: cage.temperature,
-            humidity: cage.humidity,
-            pressure: cage.pressure,
-            ammonia: cage.ammonia,
-            co2: cage.co2,
-            object_recognition: cage.object_recognition,
-            timestamp: cage.timestamp.to_rfc3339(),
-            created_at: cage.created_at.to_rfc3339(),
-            updated_at: cage.updated_at.to_rfc3339(),
+            temperature: reading.temperature,
+            humidity: reading.humidity,
+            pressure: reading.pressure,
+            ammonia: reading.ammonia,
+            co2: reading.co2,
+            object_recognition: reading.object_recognition,
+            timestamp: reading.timestamp.to_rfc3339(),
+            created_at: reading.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RotateDeviceTokenDto {
+    /// How long the old token keeps working so the device can be updated.
+    #[validate(range(max = 604800, message = "grace period can not exceed 7 days"))]
+    #[serde(default)]
+    pub grace_period_secs: u64,
+}
+
+#[derive(Serialize)]
+pub struct RotatedDeviceTokenDto {
+    pub cage_id: String,
+    pub device_token: String,
+    pub version: u32,
+    pub previous_token_valid_until: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DeviceTokenVersionDto {
+    pub version: u32,
+    pub current: bool,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+impl DeviceTokenVersionDto {
+    pub fn from_version(version: DeviceTokenVersion, current_version: Option<u32>) -> Self {
+        DeviceTokenVersionDto {
+            current: current_version == Some(version.version),
+            version: version.version,
+            created_at: version.created_at.to_rfc3339(),
+            expires_at: version.expires_at.map(|at| at.to_rfc3339()),
+            revoked_at: version.revoked_at.map(|at| at.to_rfc3339()),
         }
     }
 }
 
+#[derive(Deserialize, Validate)]
+pub struct DeviceHeartbeatDto {
+    #[validate(length(min = 1, max = 64, message = "firmware version is required"))]
+    pub firmware_version: String,
+    pub uptime_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatusDto {
+    pub cage_id: String,
+    pub assigned_monitor: String,
+    pub online: bool,
+    pub firmware_version: Option<String>,
+    pub uptime_secs: Option<u64>,
+    pub last_seen_at: String,
+    pub offline_since: Option<String>,
+}
+
+impl DeviceStatusDto {
+    pub fn from_device(cage: &Cage, device: SpmDevice) -> Self {
+        DeviceStatusDto {
+            cage_id: device.id,
+            assigned_monitor: cage.assigned_monitor.clone(),
+            online: device.online,
+            firmware_version: device.firmware_version,
+            uptime_secs: device.uptime_secs,
+            last_seen_at: device.last_seen_at.to_rfc3339(),
+            offline_since: device.offline_since.map(|at| at.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingStatus {
+    Online,
+    Stale,
+    NoData,
+}
+
+#[derive(Serialize)]
+pub struct LatestCageReadingDto {
+    pub cage_id: String,
+    pub assigned_monitor: String,
+    pub livestock_no: u32,
+    pub status: ReadingStatus,
+    pub age_secs: Option<i64>,
+    pub reading: Option<CageDto>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CageCsvDto {
     #[serde(rename = "_id")]
@@ -128,13 +341,12 @@ pub struct CageCsvDto {
     pub healthy: f32,
     pub timestamp: String,
     pub created_at: String,
-    pub updated_at: String,
 }
 
-impl From<Cage> for CageCsvDto {
-    fn from(cage: Cage) -> Self {
+impl From<CageDto> for CageCsvDto {
+    fn from(cage: CageDto) -> Self {
         CageCsvDto {
-            id: cage.id.to_string(),
+            id: cage.id,
             cage_id: cage.cage_id,
             assigned_monitor: cage.assigned_monitor,
             livestock_no: cage.livestock_no,
@@ -147,9 +359,8 @@ impl From<Cage> for CageCsvDto {
             newcastle: cage.object_recognition.newcastle,
             salmonella: cage.object_recognition.salmonella,
             healthy: cage.object_recognition.healthy,
-            timestamp: cage.timestamp.to_rfc3339(),
-            created_at: cage.created_at.to_rfc3339(),
-            updated_at: cage.updated_at.to_rfc3339(),
+            timestamp: cage.timestamp,
+            created_at: cage.created_at,
         }
     }
 }
@@ -192,20 +403,116 @@ pub struct DownloadCageReportDto {
     pub file_type: String,
 }
 
+#[derive(Validate, Deserialize)]
+#[validate(schema(function = "validate_sensor_range"))]
+pub struct SensorRangeDto {
+    pub lower: Option<f32>,
+    pub upper: Option<f32>,
+    #[validate(range(min = 0.0, message = "hysteresis can not be negative"))]
+    #[serde(default)]
+    pub hysteresis: f32,
+    #[validate(range(max = 604800, message = "minimum duration can not exceed 7 days"))]
+    #[serde(default)]
+    pub min_duration_secs: u64,
+}
+
+impl SensorRangeDto {
+    pub fn to_model(&self) -> SensorRange {
+        SensorRange {
+            lower: self.lower,
+            upper: self.upper,
+            hysteresis: self.hysteresis,
+            min_duration_secs: self.min_duration_secs,
+        }
+    }
+}
+
+fn validate_sensor_range(range: &SensorRangeDto) -> Result<(), ValidationError> {
+    let values = [range.lower, range.upper, Some(range.hysteresis)];
+    if values.into_iter().flatten().any(|value| !value.is_finite()) {
+        return Err(ValidationError::new("range")
+            .with_message("range values must be finite numbers".into()));
+    }
+    if let (Some(lower), Some(upper)) = (range.lower, range.upper) {
+        if lower >= upper {
+            return Err(ValidationError::new("range")
+                .with_message("lower bound must be less than upper bound".into()));
+        }
+        if range.hysteresis * 2.0 >= upper - lower {
+            return Err(ValidationError::new("hysteresis")
+                .with_message("hysteresis must fit inside the range".into()));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Validate, Deserialize)]
+pub struct DiseaseThresholdDto {
+    #[validate(range(min = 0.0, max = 1.0, message = "threshold must be between 0 and 1"))]
+    pub threshold: f32,
+    #[validate(range(min = 1, message = "consecutive readings must be at least 1"))]
+    #[serde(default = "default_consecutive_readings")]
+    pub consecutive_readings: u32,
+}
+
+fn default_consecutive_readings() -> u32 {
+    1
+}
+
+impl DiseaseThresholdDto {
+    pub fn to_model(&self) -> DiseaseThreshold {
+        DiseaseThreshold {
+            threshold: Some(self.threshold),
+            consecutive_readings: self.consecutive_readings,
+        }
+    }
+}
+
 #[derive(Validate, Deserialize)]
 pub struct UpdateHealthSettingsDto {
-    pub temperature: f32,
-    pub pressure: f32,
-    pub humidity: f32,
+    #[validate(nested)]
+    pub temperature: Option<SensorRangeDto>,
+    #[validate(nested)]
+    pub pressure: Option<SensorRangeDto>,
+    #[validate(nested)]
+    pub humidity: Option<SensorRangeDto>,
+    #[validate(nested)]
+    pub ammonia: Option<SensorRangeDto>,
+    #[validate(nested)]
+    pub co2: Option<SensorRangeDto>,
+    #[validate(nested)]
+    pub coccidiosis: Option<DiseaseThresholdDto>,
+    #[validate(nested)]
+    pub newcastle: Option<DiseaseThresholdDto>,
+    #[validate(nested)]
+    pub salmonella: Option<DiseaseThresholdDto>,
 }
 
 impl UpdateHealthSettingsDto {
     pub fn to_model(&self, cage_id: String) -> HealthSettings {
+        let to_range = |range: &Option<SensorRangeDto>| {
+            range
+                .as_ref()
+                .map(SensorRangeDto::to_model)
+                .unwrap_or_default()
+        };
+        let to_threshold = |threshold: &Option<DiseaseThresholdDto>| {
+            threshold
+                .as_ref()
+                .map(DiseaseThresholdDto::to_model)
+                .unwrap_or_default()
+        };
+
         HealthSettings {
             cage_id,
-            temperature: self.temperature,
-            pressure: self.pressure,
-            humidity: self.humidity,
+            temperature: to_range(&self.temperature),
+            pressure: to_range(&self.pressure),
+            humidity: to_range(&self.humidity),
+            ammonia: to_range(&self.ammonia),
+            co2: to_range(&self.co2),
+            coccidiosis: to_threshold(&self.coccidiosis),
+            newcastle: to_threshold(&self.newcastle),
+            salmonella: to_threshold(&self.salmonella),
         }
     }
 }
@@ -221,3 +528,171 @@ pub struct CagePagination {
     pub offset: u64,
     pub limit: u64,
 }
+
+#[derive(Deserialize, Validate)]
+pub struct DiseaseRiskQuery {
+    #[validate(range(min = 1, max = 720, message = "window must be between 1 and 720 hours"))]
+    pub window_hours: u32,
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        message = "bucket must be between 1 and 1440 minutes"
+    ))]
+    pub bucket_minutes: u32,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskTrend {
+    Rising,
+    Falling,
+    Steady,
+}
+
+#[derive(Serialize)]
+pub struct DiseaseRiskSummary {
+    pub disease: String,
+    pub latest: f32,
+    pub average: f32,
+    pub peak: f32,
+    pub trend: RiskTrend,
+}
+
+#[derive(Serialize)]
+pub struct DiseaseRiskPoint {
+    pub bucket_start: String,
+    pub readings: u32,
+    pub coccidiosis: f32,
+    pub newcastle: f32,
+    pub salmonella: f32,
+    pub healthy: f32,
+}
+
+impl From<DiseaseRiskBucket> for DiseaseRiskPoint {
+    fn from(bucket: DiseaseRiskBucket) -> Self {
+        DiseaseRiskPoint {
+            bucket_start: bucket.bucket_start.to_rfc3339(),
+            readings: bucket.readings,
+            coccidiosis: bucket.coccidiosis,
+            newcastle: bucket.newcastle,
+            salmonella: bucket.salmonella,
+            healthy: bucket.healthy,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DiseaseRiskResponse {
+    pub cage_id: String,
+    pub window_start: String,
+    pub window_end: String,
+    pub summaries: Vec<DiseaseRiskSummary>,
+    pub points: Vec<DiseaseRiskPoint>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CageSeriesQuery {
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>,
+    #[validate(length(min = 2, message = "bucket is required"))]
+    pub bucket: String,
+    /// Comma separated metric names, defaulting to every sensor metric.
+    pub metrics: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReadingBucketDto {
+    pub bucket_start: String,
+    pub count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<MetricStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<MetricStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<MetricStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ammonia: Option<MetricStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub co2: Option<MetricStats>,
+}
+
+impl From<ReadingBucket> for ReadingBucketDto {
+    fn from(bucket: ReadingBucket) -> Self {
+        ReadingBucketDto {
+            bucket_start: bucket.bucket_start.to_rfc3339(),
+            count: bucket.count,
+            temperature: bucket.temperature,
+            humidity: bucket.humidity,
+            pressure: bucket.pressure,
+            ammonia: bucket.ammonia,
+            co2: bucket.co2,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CageSeriesResponse {
+    pub cage_id: String,
+    pub start_date: String,
+    pub end_date: String,
+    pub bucket: String,
+    pub metrics: Vec<String>,
+    pub buckets: Vec<ReadingBucketDto>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RegisterDevicesDto {
+    #[validate(length(
+        min = 1,
+        max = 500,
+        message = "between 1 and 500 serial numbers are required"
+    ))]
+    pub serial_numbers: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RegisteredDeviceDto {
+    pub serial_number: String,
+    pub claim_code: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ClaimDeviceDto {
+    #[validate(length(min = 1, message = "claim_code is required"))]
+    pub claim_code: String,
+    #[validate(length(min = 1, message = "cageID is required"))]
+    pub cage_id: String,
+    pub livestock_no: u32,
+}
+
+#[derive(Serialize)]
+pub struct ClaimedDeviceDto {
+    pub serial_number: String,
+    pub cage_id: String,
+    pub claimed_at: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ProvisionDeviceDto {
+    #[validate(length(min = 1, message = "serial_number is required"))]
+    pub serial_number: String,
+    #[validate(length(min = 1, message = "claim_code is required"))]
+    pub claim_code: String,
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvisionStatus {
+    /// Nobody has claimed the device yet; the device should ask again.
+    Pending,
+    Provisioned,
+}
+
+#[derive(Serialize)]
+pub struct ProvisionDeviceResponse {
+    pub status: ProvisionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cage_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_token: Option<String>,
+}
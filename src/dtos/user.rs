@@ -1,6 +1,6 @@
 use bcrypt::hash;
 use chrono::Utc;
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, oid::ObjectId, Document};
 use serde::Deserialize;
 use validator::Validate;
 
@@ -31,6 +31,7 @@ impl CreateAdminUserDto {
             phone_number: self.phone_number,
             password: hashed_password,
             spm_id: None,
+            disabled: false,
             r#type: UserType::Admin.to_string(),
             created_by: None,
             created_customers: Some(vec![]),
@@ -40,6 +41,42 @@ impl CreateAdminUserDto {
     }
 }
 
+/// Creates the first super-admin. Only accepted with the `SETUP_TOKEN` the
+/// server was started with, and only once.
+#[derive(Deserialize, Validate)]
+pub struct BootstrapSuperAdminDto {
+    #[validate(length(min = 1, message = "Setup token is required"))]
+    pub setup_token: String,
+    #[validate(length(min = 1, message = "Name is required"))]
+    name: String,
+    #[validate(email(message = "Email is invalid"))]
+    email: String,
+    #[validate(length(min = 1, message = "Phone number is required"))]
+    phone_number: String,
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    password: String,
+}
+
+impl BootstrapSuperAdminDto {
+    pub fn to_model(self) -> Result<User, ApiErrorResponse> {
+        let hashed_password = hash(self.password, 12).map_err(internal_error)?;
+        Ok(User {
+            id: ObjectId::new(),
+            name: self.name,
+            email: self.email,
+            phone_number: self.phone_number,
+            password: hashed_password,
+            spm_id: None,
+            disabled: false,
+            r#type: UserType::SuperAdmin.to_string(),
+            created_by: None,
+            created_customers: Some(vec![]),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+    }
+}
+
 #[derive(Deserialize, Validate)]
 pub struct CreateCustomerDto {
     #[validate(length(min = 1, message = "Name is required"))]
@@ -50,12 +87,15 @@ pub struct CreateCustomerDto {
     phone_number: String,
     #[validate(length(min = 1, message = "Spm_id is required"))]
     spm_id: String,
+    /// `customer` when left out, or `owner`.
+    pub role: Option<String>,
 }
 
 impl CreateCustomerDto {
-    pub fn to_model(self, admin_id: ObjectId) -> Result<User, ApiErrorResponse> {
-        let password = generate_password(12);
-        let hashed_password = hash(password, 12).map_err(internal_error)?;
+    /// The customer sets their own password by accepting an invitation, so
+    /// the stored password is a random one that nobody is ever told.
+    pub fn to_model(self, admin_id: ObjectId, role: UserType) -> Result<User, ApiErrorResponse> {
+        let hashed_password = hash(generate_password(32), 12).map_err(internal_error)?;
 
         Ok(User {
             id: ObjectId::new(),
@@ -64,7 +104,8 @@ impl CreateCustomerDto {
             phone_number: self.phone_number,
             password: hashed_password,
             spm_id: Some(self.spm_id),
-            r#type: UserType::Customer.to_string(),
+            disabled: false,
+            r#type: role.to_string(),
             created_by: Some(admin_id),
             created_customers: None,
             created_at: Utc::now(),
@@ -72,3 +113,71 @@ impl CreateCustomerDto {
         })
     }
 }
+
+/// Farm staff work on the cages of the user who adds them.
+#[derive(Deserialize, Validate)]
+pub struct CreateStaffDto {
+    #[validate(length(min = 1, message = "Name is required"))]
+    name: String,
+    #[validate(email(message = "Email is invalid"))]
+    email: String,
+    #[validate(length(min = 1, message = "Phone number is required"))]
+    phone_number: String,
+    /// One of `farm_manager`, `worker` or `veterinarian`.
+    #[validate(length(min = 1, message = "Role is required"))]
+    pub role: String,
+}
+
+impl CreateStaffDto {
+    /// Like customers, staff set their password through an invitation.
+    pub fn to_model(self, manager_id: ObjectId, role: UserType) -> Result<User, ApiErrorResponse> {
+        let hashed_password = hash(generate_password(32), 12).map_err(internal_error)?;
+
+        Ok(User {
+            id: ObjectId::new(),
+            name: self.name,
+            email: self.email,
+            phone_number: self.phone_number,
+            password: hashed_password,
+            spm_id: None,
+            disabled: false,
+            r#type: role.to_string(),
+            created_by: Some(manager_id),
+            created_customers: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateUserDto {
+    #[validate(length(min = 1, message = "Name is required"))]
+    pub name: Option<String>,
+    #[validate(email(message = "Email is invalid"))]
+    pub email: Option<String>,
+    #[validate(length(min = 1, message = "Phone number is required"))]
+    pub phone_number: Option<String>,
+    #[validate(length(min = 1, message = "Role is required"))]
+    pub role: Option<String>,
+}
+
+impl UpdateUserDto {
+    /// The fields to `$set`; only those present in the request.
+    pub fn to_document(&self) -> Document {
+        let mut update = doc! {};
+        if let Some(name) = &self.name {
+            update.insert("name", name);
+        }
+        if let Some(email) = &self.email {
+            update.insert("email", email);
+        }
+        if let Some(phone_number) = &self.phone_number {
+            update.insert("phone_number", phone_number);
+        }
+        if let Some(role) = &self.role {
+            update.insert("type", role);
+        }
+        update
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    middleware,
+    routing::{get, post},
+    Extension, Router,
+};
+
+use crate::{
+    dtos::invitation_dtos::{AcceptInvitationDto, InvitationDto},
+    middleware::auth_middleware::requires_permission,
+    models::user::{AuthUserDto, Permission},
+    services::invitation_service::InvitationService,
+    utils::{
+        response::{ApiErrorResponse, ApiSuccessResponse},
+        validators::ValidatedJson,
+    },
+    AppState,
+};
+
+pub fn invitation_endpoints() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/",
+            get(fetch_pending_invitations).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        .route(
+            "/:invitation_id/resend",
+            post(resend_invitation).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        .route(
+            "/:invitation_id/revoke",
+            post(revoke_invitation).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        // Redeemed by the invited user before they have a password.
+        .route("/accept", post(accept_invitation))
+}
+
+pub async fn fetch_pending_invitations(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+) -> Result<ApiSuccessResponse<Vec<InvitationDto>>, ApiErrorResponse> {
+    let invitation_service = InvitationService::new(app_state.mongo_client.clone());
+    invitation_service
+        .fetch_pending_invitations(auth_user.id)
+        .await
+}
+
+pub async fn resend_invitation(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(invitation_id): Path<String>,
+) -> Result<ApiSuccessResponse<InvitationDto>, ApiErrorResponse> {
+    let invitation_service = InvitationService::new(app_state.mongo_client.clone());
+    invitation_service
+        .resend_invitation(auth_user.id, invitation_id)
+        .await
+}
+
+pub async fn revoke_invitation(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(invitation_id): Path<String>,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let invitation_service = InvitationService::new(app_state.mongo_client.clone());
+    invitation_service
+        .revoke_invitation(auth_user.id, invitation_id)
+        .await
+}
+
+pub async fn accept_invitation(
+    State(app_state): State<Arc<AppState>>,
+    ValidatedJson(payload): ValidatedJson<AcceptInvitationDto>,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let invitation_service = InvitationService::new(app_state.mongo_client.clone());
+    invitation_service.accept_invitation(payload).await
+}
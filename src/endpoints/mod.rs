@@ -1,3 +1,7 @@
 pub mod auth_endpoints;
+pub mod invitation_endpoints;
+pub mod notification_endpoints;
+pub mod plan_endpoints;
+pub mod provisioning_endpoints;
 pub mod spm_endpoints;
 pub mod user_endpoints;
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    middleware,
+    routing::{delete, get},
+    Extension, Router,
+};
+
+use crate::{
+    dtos::notification_dtos::{CreateNotificationChannelDto, NotificationChannelDto},
+    middleware::auth_middleware,
+    models::user::AuthUserDto,
+    services::notification_service::NotificationService,
+    utils::{
+        response::{ApiErrorResponse, ApiSuccessResponse},
+        validators::ValidatedJson,
+    },
+    AppState,
+};
+
+pub fn notification_endpoints() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/channels",
+            get(fetch_users_channels)
+                .post(create_channel)
+                .layer(middleware::from_fn(auth_middleware::requires_auth)),
+        )
+        .route(
+            "/channels/:channel_id",
+            delete(delete_channel).layer(middleware::from_fn(auth_middleware::requires_auth)),
+        )
+}
+
+pub async fn create_channel(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedJson(payload): ValidatedJson<CreateNotificationChannelDto>,
+) -> Result<ApiSuccessResponse<NotificationChannelDto>, ApiErrorResponse> {
+    let notification_service = NotificationService::new(app_state.mongo_client.clone());
+    notification_service
+        .create_channel(auth_user.id, payload)
+        .await
+}
+
+pub async fn fetch_users_channels(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+) -> Result<ApiSuccessResponse<Vec<NotificationChannelDto>>, ApiErrorResponse> {
+    let notification_service = NotificationService::new(app_state.mongo_client.clone());
+    notification_service
+        .fetch_users_channels(auth_user.id)
+        .await
+}
+
+pub async fn delete_channel(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(channel_id): Path<String>,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let notification_service = NotificationService::new(app_state.mongo_client.clone());
+    notification_service
+        .delete_channel(auth_user.id, channel_id)
+        .await
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    middleware,
+    routing::get,
+    Router,
+};
+
+use crate::{
+    dtos::plan_dtos::{PlanDto, UpdatePlanDto},
+    middleware::auth_middleware::requires_permission,
+    models::user::Permission,
+    services::plan_service::PlanService,
+    utils::{
+        response::{ApiErrorResponse, ApiSuccessResponse},
+        validators::ValidatedJson,
+    },
+    AppState,
+};
+
+pub fn plan_endpoints() -> Router<Arc<AppState>> {
+    Router::new().route(
+        "/:admin_id",
+        get(fetch_plan)
+            .put(update_plan)
+            .layer(middleware::from_fn(requires_permission(
+                Permission::PlansManage,
+            ))),
+    )
+}
+
+pub async fn fetch_plan(
+    State(app_state): State<Arc<AppState>>,
+    Path(admin_id): Path<String>,
+) -> Result<ApiSuccessResponse<PlanDto>, ApiErrorResponse> {
+    let plan_service = PlanService::new(app_state.mongo_client.clone());
+    plan_service.fetch_plan(admin_id).await
+}
+
+pub async fn update_plan(
+    State(app_state): State<Arc<AppState>>,
+    Path(admin_id): Path<String>,
+    ValidatedJson(payload): ValidatedJson<UpdatePlanDto>,
+) -> Result<ApiSuccessResponse<PlanDto>, ApiErrorResponse> {
+    let plan_service = PlanService::new(app_state.mongo_client.clone());
+    plan_service.update_plan(admin_id, payload).await
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use axum::{extract::State, middleware, routing::post, Extension, Router};
+
+use crate::{
+    dtos::spm_dtos::{
+        ClaimDeviceDto, ClaimedDeviceDto, ProvisionDeviceDto, ProvisionDeviceResponse,
+        RegisterDevicesDto, RegisteredDeviceDto,
+    },
+    middleware::auth_middleware::requires_permission,
+    models::user::{AuthUserDto, Permission},
+    services::provisioning_service::ProvisioningService,
+    utils::{
+        response::{ApiErrorResponse, ApiSuccessResponse},
+        validators::ValidatedJson,
+    },
+    AppState,
+};
+
+pub fn provisioning_endpoints() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/devices",
+            post(register_devices).layer(middleware::from_fn(requires_permission(
+                Permission::DevicesManage,
+            ))),
+        )
+        .route(
+            "/claims",
+            post(claim_device).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        // Called by the device itself on first boot, before it has a token.
+        .route("/bootstrap", post(provision_device))
+}
+
+pub async fn register_devices(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedJson(payload): ValidatedJson<RegisterDevicesDto>,
+) -> Result<ApiSuccessResponse<Vec<RegisteredDeviceDto>>, ApiErrorResponse> {
+    let provisioning_service = ProvisioningService::new(app_state.mongo_client.clone());
+    provisioning_service
+        .register_devices(auth_user.id, payload)
+        .await
+}
+
+pub async fn claim_device(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedJson(payload): ValidatedJson<ClaimDeviceDto>,
+) -> Result<ApiSuccessResponse<ClaimedDeviceDto>, ApiErrorResponse> {
+    let provisioning_service = ProvisioningService::new(app_state.mongo_client.clone());
+    provisioning_service
+        .claim_device(auth_user.id, payload)
+        .await
+}
+
+pub async fn provision_device(
+    State(app_state): State<Arc<AppState>>,
+    ValidatedJson(payload): ValidatedJson<ProvisionDeviceDto>,
+) -> Result<ApiSuccessResponse<ProvisionDeviceResponse>, ApiErrorResponse> {
+    let provisioning_service = ProvisioningService::new(app_state.mongo_client.clone());
+    provisioning_service.provision_device(payload).await
+}
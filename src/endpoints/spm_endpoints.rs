@@ -1,16 +1,25 @@
 use std::{str::FromStr, sync::Arc};
 
 use crate::{
-    dtos::spm_dtos::{
-        AddNewCageDto, CagePagination, DownloadCageReportDto, FileType, UpdateCageDto,
-        UpdateHealthSettingsDto, UserCageDataResponse,
+    dtos::{
+        alert_dtos::{AddAlertCommentDto, AlertDto, AlertQuery, UserAlertsResponse},
+        spm_dtos::{
+            AddNewCageDto, BatchUpdateCageDto, BatchUpdateCageResponse, BulkAddCagesDto,
+            BulkAddCagesResponse, CageInfoDto, CagePagination, CageSeriesQuery, CageSeriesResponse,
+            DeviceHeartbeatDto, DeviceStatusDto, DeviceTokenVersionDto, DiseaseRiskQuery,
+            DiseaseRiskResponse, DownloadCageReportDto, EditCageDto, FileType, ImportCagesResponse,
+            LatestCageReadingDto, ReassignCageDto, RotateDeviceTokenDto, RotatedDeviceTokenDto,
+            UpdateCageDto, UpdateHealthSettingsDto, UserCageDataResponse,
+        },
     },
-    middleware::auth_middleware::{self, SpmDeviceAuth},
+    middleware::auth_middleware::{self, requires_permission, AuthenticatedDevice, AuthorizedCage},
     models::{
         spm::{CageWithDeviceToken, HealthSettings},
-        user::AuthUserDto,
+        user::{AuthUserDto, Permission},
+    },
+    services::{
+        alert_service::AlertService, spm_service::SpmService, telemetry_service::TelemetryService,
     },
-    services::spm_service::SpmService,
     utils::{
         error_handler::internal_error,
         response::{
@@ -22,10 +31,10 @@ use crate::{
     AppState,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{Path, State, WebSocketUpgrade},
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, patch, post},
     Extension, Router,
 };
 
@@ -34,32 +43,182 @@ pub fn spm_endpoints() -> Router<Arc<AppState>> {
         .route(
             "/cages",
             get(fetch_all_users_cage_data)
-                .post(add_new_cage)
-                .layer(middleware::from_fn(auth_middleware::requires_auth)),
+                .layer(middleware::from_fn(requires_permission(
+                    Permission::CagesRead,
+                )))
+                .merge(
+                    post(add_new_cage).layer(middleware::from_fn(requires_permission(
+                        Permission::CagesWrite,
+                    ))),
+                ),
+        )
+        .route(
+            "/cages/bulk",
+            post(add_new_cages_in_bulk).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/cages/import",
+            post(import_cages_from_csv).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/provisioning-sheets/:sheet_id",
+            get(download_provisioning_sheet).layer(middleware::from_fn(requires_permission(
+                Permission::DeviceTokensManage,
+            ))),
+        )
+        .route(
+            "/cages/latest",
+            get(fetch_latest_users_cage_readings).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
+        )
+        .route(
+            "/alerts",
+            get(fetch_users_alerts).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
+        )
+        .route(
+            "/alerts/:alert_id",
+            get(get_users_alert).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
+        )
+        .route(
+            "/alerts/:alert_id/acknowledge",
+            post(acknowledge_users_alert).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/alerts/:alert_id/resolve",
+            post(resolve_users_alert).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/alerts/:alert_id/comments",
+            post(add_users_alert_comment).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/live",
+            get(stream_live_cage_data)
+                .layer(middleware::from_fn(auth_middleware::requires_ws_auth)),
         )
         .route(
             "/:cage_id",
-            post(update_cage_info).layer(middleware::from_fn(auth_middleware::requires_spm_auth)),
+            post(update_cage_info)
+                .layer(middleware::from_fn(auth_middleware::requires_spm_auth))
+                .merge(
+                    patch(edit_users_cage).layer(middleware::from_fn(requires_permission(
+                        Permission::CagesWrite,
+                    ))),
+                )
+                .merge(
+                    delete(delete_users_cage).layer(middleware::from_fn(requires_permission(
+                        Permission::CagesDelete,
+                    ))),
+                ),
+        )
+        .route(
+            "/:cage_id/reassign",
+            post(reassign_users_cage).layer(middleware::from_fn(requires_permission(
+                Permission::CagesDelete,
+            ))),
+        )
+        .route(
+            "/:cage_id/archive",
+            post(archive_users_cage).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/:cage_id/unarchive",
+            post(unarchive_users_cage).layer(middleware::from_fn(requires_permission(
+                Permission::CagesWrite,
+            ))),
+        )
+        .route(
+            "/:cage_id/batch",
+            post(batch_update_cage_info)
+                .layer(middleware::from_fn(auth_middleware::requires_spm_auth)),
+        )
+        .route(
+            "/:cage_id/heartbeat",
+            post(record_device_heartbeat)
+                .layer(middleware::from_fn(auth_middleware::requires_spm_auth)),
+        )
+        .route(
+            "/:cage_id/device",
+            get(get_users_cage_device).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
+        )
+        .route(
+            "/:cage_id/device-token",
+            get(fetch_device_token_history).layer(middleware::from_fn(requires_permission(
+                Permission::DeviceTokensManage,
+            ))),
+        )
+        .route(
+            "/:cage_id/device-token/rotate",
+            post(rotate_device_token).layer(middleware::from_fn(requires_permission(
+                Permission::DeviceTokensManage,
+            ))),
+        )
+        .route(
+            "/:cage_id/device-token/revoke",
+            post(revoke_device_token).layer(middleware::from_fn(requires_permission(
+                Permission::DeviceTokensManage,
+            ))),
         )
         .route(
             "/report",
-            post(export_cage_data).layer(middleware::from_fn(auth_middleware::requires_auth)),
+            post(export_cage_data).layer(middleware::from_fn(requires_permission(
+                Permission::ReportsExport,
+            ))),
         )
         .route(
             "/export/csv",
-            get(download_cage_report_in_csv_format)
-                .layer(middleware::from_fn(auth_middleware::requires_auth)),
+            get(download_cage_report_in_csv_format).layer(middleware::from_fn(
+                requires_permission(Permission::ReportsExport),
+            )),
         )
         .route(
             "/export/pdf",
-            get(download_cage_report_in_pdf_format)
-                .layer(middleware::from_fn(auth_middleware::requires_auth)),
+            get(download_cage_report_in_pdf_format).layer(middleware::from_fn(
+                requires_permission(Permission::ReportsExport),
+            )),
         )
         .route(
             "/:cage_id/health-settings",
-            post(update_users_cage_health_settings)
-                .get(get_users_cage_health_settings)
-                .layer(middleware::from_fn(auth_middleware::requires_auth)),
+            get(get_users_cage_health_settings)
+                .layer(middleware::from_fn(requires_permission(
+                    Permission::CagesRead,
+                )))
+                .merge(
+                    post(update_users_cage_health_settings).layer(middleware::from_fn(
+                        requires_permission(Permission::SettingsWrite),
+                    )),
+                ),
+        )
+        .route(
+            "/:cage_id/series",
+            get(fetch_users_cage_series).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
+        )
+        .route(
+            "/:cage_id/disease-risk",
+            get(fetch_users_cage_disease_risk).layer(middleware::from_fn(requires_permission(
+                Permission::CagesRead,
+            ))),
         )
 }
 
@@ -72,18 +231,174 @@ pub async fn add_new_cage(
     spm_service.add_new_cage(auth_user.id, payload).await
 }
 
+pub async fn add_new_cages_in_bulk(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedJson(payload): ValidatedJson<BulkAddCagesDto>,
+) -> Result<ApiSuccessResponse<BulkAddCagesResponse>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .add_new_cages_in_bulk(auth_user.id, payload)
+        .await
+}
+
+pub async fn import_cages_from_csv(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    csv_data: String,
+) -> Result<ApiSuccessResponse<ImportCagesResponse>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .import_cages_from_csv(auth_user.id, csv_data)
+        .await
+}
+
+pub async fn download_provisioning_sheet(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(sheet_id): Path<String>,
+) -> Result<SpmDownloadPdfSuccessResponse, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .download_provisioning_sheet(auth_user.id, sheet_id)
+        .await
+}
+
 pub async fn update_cage_info(
     State(app_sate): State<Arc<AppState>>,
-    Extension(spm_device_auth): Extension<SpmDeviceAuth>,
-    Path(cage_id): Path<String>,
+    authenticated_device: AuthenticatedDevice,
     ValidatedJson(payload): ValidatedJson<UpdateCageDto>,
 ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
     let spm_service = SpmService::new(app_sate.mongo_client.clone());
     spm_service
-        .update_cage_info(cage_id, payload, spm_device_auth.token)
+        .update_cage_info(authenticated_device.cage, payload, &app_sate.telemetry_hub)
         .await
 }
 
+pub async fn batch_update_cage_info(
+    State(app_sate): State<Arc<AppState>>,
+    authenticated_device: AuthenticatedDevice,
+    ValidatedJson(payload): ValidatedJson<BatchUpdateCageDto>,
+) -> Result<ApiSuccessResponse<BatchUpdateCageResponse>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_sate.mongo_client.clone());
+    spm_service
+        .batch_update_cage_info(authenticated_device.cage, payload, &app_sate.telemetry_hub)
+        .await
+}
+
+pub async fn record_device_heartbeat(
+    State(app_state): State<Arc<AppState>>,
+    authenticated_device: AuthenticatedDevice,
+    ValidatedJson(payload): ValidatedJson<DeviceHeartbeatDto>,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .record_device_heartbeat(authenticated_device.cage, payload, &app_state.telemetry_hub)
+        .await
+}
+
+pub async fn edit_users_cage(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+    ValidatedJson(payload): ValidatedJson<EditCageDto>,
+) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .edit_users_cage(authorized_cage.cage, payload)
+        .await
+}
+
+pub async fn reassign_users_cage(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    authorized_cage: AuthorizedCage,
+    ValidatedJson(payload): ValidatedJson<ReassignCageDto>,
+) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .reassign_users_cage(auth_user.id, authorized_cage.cage, payload)
+        .await
+}
+
+pub async fn archive_users_cage(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .set_users_cage_archived(authorized_cage.cage, true)
+        .await
+}
+
+pub async fn unarchive_users_cage(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .set_users_cage_archived(authorized_cage.cage, false)
+        .await
+}
+
+pub async fn delete_users_cage(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service.delete_users_cage(authorized_cage.cage).await
+}
+
+pub async fn get_users_cage_device(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<DeviceStatusDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .get_users_cage_device(authorized_cage.cage)
+        .await
+}
+
+pub async fn rotate_device_token(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+    ValidatedJson(payload): ValidatedJson<RotateDeviceTokenDto>,
+) -> Result<ApiSuccessResponse<RotatedDeviceTokenDto>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .rotate_device_token(authorized_cage.cage, payload)
+        .await
+}
+
+pub async fn revoke_device_token(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service.revoke_device_token(authorized_cage.cage).await
+}
+
+pub async fn fetch_device_token_history(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+) -> Result<ApiSuccessResponse<Vec<DeviceTokenVersionDto>>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .fetch_device_token_history(authorized_cage.cage)
+        .await
+}
+
+pub async fn stream_live_cage_data(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let telemetry_service = TelemetryService::new(
+        app_state.mongo_client.clone(),
+        app_state.telemetry_hub.clone(),
+    );
+    ws.on_upgrade(move |socket| telemetry_service.handle_socket(socket, auth_user.id))
+}
+
 pub async fn fetch_all_users_cage_data(
     State(app_sate): State<Arc<AppState>>,
     Extension(auth_user): Extension<AuthUserDto>,
@@ -95,6 +410,16 @@ pub async fn fetch_all_users_cage_data(
         .await
 }
 
+pub async fn fetch_latest_users_cage_readings(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+) -> Result<ApiSuccessResponse<Vec<LatestCageReadingDto>>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .fetch_latest_users_cage_readings(auth_user.id)
+        .await
+}
+
 pub async fn download_cage_report_in_csv_format(
     State(app_sate): State<Arc<AppState>>,
     Extension(auth_user): Extension<AuthUserDto>,
@@ -139,23 +464,97 @@ pub async fn export_cage_data(
 
 pub async fn update_users_cage_health_settings(
     State(app_sate): State<Arc<AppState>>,
-    Extension(_): Extension<AuthUserDto>,
-    Path(cage_id): Path<String>,
+    authorized_cage: AuthorizedCage,
     ValidatedJson(payload): ValidatedJson<UpdateHealthSettingsDto>,
 ) -> Result<ApiSuccessResponse<HealthSettings>, ApiErrorResponse> {
     let spm_service = SpmService::new(app_sate.mongo_client.clone());
     spm_service
-        .update_cage_health_settings(cage_id, payload)
+        .update_cage_health_settings(authorized_cage.cage, payload)
         .await
 }
 
 pub async fn get_users_cage_health_settings(
     State(app_sate): State<Arc<AppState>>,
-    Extension(_): Extension<AuthUserDto>,
-    Path(cage_id): Path<String>,
+    authorized_cage: AuthorizedCage,
 ) -> Result<ApiSuccessResponse<HealthSettings>, ApiErrorResponse> {
     let spm_service = SpmService::new(app_sate.mongo_client.clone());
     spm_service
-        .get_cage_health_settings_by_cage_id(cage_id)
+        .get_cage_health_settings_by_cage_id(authorized_cage.cage)
+        .await
+}
+
+pub async fn fetch_users_cage_series(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+    ValidatedQuery(cage_series_query): ValidatedQuery<CageSeriesQuery>,
+) -> Result<ApiSuccessResponse<CageSeriesResponse>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .fetch_cage_series(authorized_cage.cage, cage_series_query)
+        .await
+}
+
+pub async fn fetch_users_cage_disease_risk(
+    State(app_state): State<Arc<AppState>>,
+    authorized_cage: AuthorizedCage,
+    ValidatedQuery(disease_risk_query): ValidatedQuery<DiseaseRiskQuery>,
+) -> Result<ApiSuccessResponse<DiseaseRiskResponse>, ApiErrorResponse> {
+    let spm_service = SpmService::new(app_state.mongo_client.clone());
+    spm_service
+        .fetch_cage_disease_risk(authorized_cage.cage, disease_risk_query)
+        .await
+}
+
+pub async fn fetch_users_alerts(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedQuery(alert_query): ValidatedQuery<AlertQuery>,
+) -> Result<ApiSuccessResponse<UserAlertsResponse>, ApiErrorResponse> {
+    let alert_service = AlertService::new(app_state.mongo_client.clone());
+    alert_service
+        .fetch_users_alerts(auth_user.id, alert_query)
+        .await
+}
+
+pub async fn get_users_alert(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(alert_id): Path<String>,
+) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+    let alert_service = AlertService::new(app_state.mongo_client.clone());
+    alert_service.get_users_alert(auth_user.id, alert_id).await
+}
+
+pub async fn acknowledge_users_alert(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(alert_id): Path<String>,
+) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+    let alert_service = AlertService::new(app_state.mongo_client.clone());
+    alert_service
+        .acknowledge_alert(auth_user.id, alert_id, &app_state.telemetry_hub)
+        .await
+}
+
+pub async fn resolve_users_alert(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(alert_id): Path<String>,
+) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+    let alert_service = AlertService::new(app_state.mongo_client.clone());
+    alert_service
+        .resolve_alert(auth_user.id, alert_id, &app_state.telemetry_hub)
+        .await
+}
+
+pub async fn add_users_alert_comment(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(alert_id): Path<String>,
+    ValidatedJson(payload): ValidatedJson<AddAlertCommentDto>,
+) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+    let alert_service = AlertService::new(app_state.mongo_client.clone());
+    alert_service
+        .add_alert_comment(auth_user.id, alert_id, payload)
         .await
 }
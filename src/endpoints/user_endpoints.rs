@@ -1,13 +1,18 @@
 use axum::{
     extract::{Path, State},
-    routing::post,
-    Router,
+    middleware,
+    routing::{get, post},
+    Extension, Router,
 };
 use std::sync::Arc;
 
 use crate::{
-    dtos::user::{CreateAdminUserDto, CreateCustomerDto},
-    models::user::NewUser,
+    dtos::user::{
+        BootstrapSuperAdminDto, CreateAdminUserDto, CreateCustomerDto, CreateStaffDto,
+        UpdateUserDto,
+    },
+    middleware::auth_middleware::requires_permission,
+    models::user::{AuthUserDto, NewUser, Permission},
     services::user_service::UserService,
     utils::{
         response::{ApiErrorResponse, ApiSuccessResponse},
@@ -18,8 +23,53 @@ use crate::{
 
 pub fn user_endpoints() -> Router<Arc<AppState>> {
     Router::new()
-        .route("/", post(create_admin_user))
-        .route("/:id/customer", post(create_customer_user))
+        .route(
+            "/",
+            post(create_admin_user)
+                .layer(middleware::from_fn(requires_permission(
+                    Permission::AdminsManage,
+                )))
+                .merge(
+                    get(fetch_managed_users).layer(middleware::from_fn(requires_permission(
+                        Permission::UsersManage,
+                    ))),
+                ),
+        )
+        // Creates the first super-admin using the server's setup token.
+        .route("/bootstrap", post(bootstrap_super_admin))
+        .route(
+            "/customers",
+            post(create_customer_user).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        .route(
+            "/staff",
+            post(create_staff_user).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        .route(
+            "/:user_id",
+            get(fetch_managed_user)
+                .patch(update_managed_user)
+                .delete(delete_managed_user)
+                .layer(middleware::from_fn(requires_permission(
+                    Permission::UsersManage,
+                ))),
+        )
+        .route(
+            "/:user_id/disable",
+            post(disable_managed_user).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
+        .route(
+            "/:user_id/enable",
+            post(enable_managed_user).layer(middleware::from_fn(requires_permission(
+                Permission::UsersManage,
+            ))),
+        )
 }
 
 async fn create_admin_user(
@@ -30,11 +80,92 @@ async fn create_admin_user(
     user_service.create_admin_user(payload).await
 }
 
+async fn bootstrap_super_admin(
+    State(app_state): State<Arc<AppState>>,
+    ValidatedJson(payload): ValidatedJson<BootstrapSuperAdminDto>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service.bootstrap_super_admin(payload).await
+}
+
 async fn create_customer_user(
     State(app_state): State<Arc<AppState>>,
-    Path(admin_id): Path<String>,
+    Extension(auth_user): Extension<AuthUserDto>,
     ValidatedJson(payload): ValidatedJson<CreateCustomerDto>,
 ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
     let user_service = UserService::new(app_state.mongo_client.clone());
-    user_service.create_customer_user(admin_id, payload).await
+    user_service
+        .create_customer_user(auth_user.id, payload)
+        .await
+}
+
+async fn create_staff_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    ValidatedJson(payload): ValidatedJson<CreateStaffDto>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service.create_staff_user(auth_user.id, payload).await
+}
+
+async fn fetch_managed_users(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+) -> Result<ApiSuccessResponse<Vec<NewUser>>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service.fetch_managed_users(auth_user.id).await
+}
+
+async fn fetch_managed_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(user_id): Path<String>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service.fetch_managed_user(auth_user.id, user_id).await
+}
+
+async fn update_managed_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(user_id): Path<String>,
+    ValidatedJson(payload): ValidatedJson<UpdateUserDto>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service
+        .update_managed_user(auth_user.id, user_id, payload)
+        .await
+}
+
+async fn disable_managed_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(user_id): Path<String>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service
+        .set_managed_user_disabled(auth_user.id, user_id, true)
+        .await
+}
+
+async fn enable_managed_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(user_id): Path<String>,
+) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service
+        .set_managed_user_disabled(auth_user.id, user_id, false)
+        .await
+}
+
+async fn delete_managed_user(
+    State(app_state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUserDto>,
+    Path(user_id): Path<String>,
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+    let user_service = UserService::new(app_state.mongo_client.clone());
+    user_service
+        .delete_managed_user(auth_user.id, user_id)
+        .await
 }
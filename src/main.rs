@@ -1,3 +1,5 @@
+#![allow(clippy::wrong_self_convention)]
+
 use std::sync::Arc;
 
 use axum::{
@@ -7,12 +9,20 @@ use axum::{
     },
     response::IntoResponse,
     routing::get,
-    Router,
+    Extension, Router,
 };
 use endpoints::{
-    auth_endpoints::auth_endpoints, spm_endpoints::spm_endpoints, user_endpoints::user_endpoints,
+    auth_endpoints::auth_endpoints, invitation_endpoints::invitation_endpoints,
+    notification_endpoints::notification_endpoints, plan_endpoints::plan_endpoints,
+    provisioning_endpoints::provisioning_endpoints, spm_endpoints::spm_endpoints,
+    user_endpoints::user_endpoints,
 };
 use mongodb::Client;
+use notifiers::Notifiers;
+use services::{
+    notification_service::NotificationService, plan_service::PlanService, spm_service::SpmService,
+    telemetry_service::TelemetryHub,
+};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -20,7 +30,9 @@ mod config;
 mod dtos;
 mod endpoints;
 mod middleware;
+mod migrations;
 mod models;
+mod notifiers;
 mod repository;
 mod services;
 mod utils;
@@ -28,6 +40,7 @@ mod utils;
 #[derive(Clone)]
 pub struct AppState {
     pub mongo_client: Arc<Client>,
+    pub telemetry_hub: TelemetryHub,
 }
 
 #[tokio::main]
@@ -41,11 +54,27 @@ async fn main() {
         .init();
 
     let mongo_client = config::database::extablish_mongodb_connection().await;
+    migrations::run_migrations(&mongo_client)
+        .await
+        .expect("Failed to run database migrations");
 
     let app_state = Arc::new(AppState {
         mongo_client: Arc::new(mongo_client),
+        telemetry_hub: TelemetryHub::new(),
     });
 
+    tokio::spawn(
+        NotificationService::new(app_state.mongo_client.clone())
+            .run_delivery_worker(Notifiers::from_env()),
+    );
+    tokio::spawn(
+        SpmService::new(app_state.mongo_client.clone()).run_offline_monitor(
+            app_state.telemetry_hub.clone(),
+            config::device::device_offline_after(),
+        ),
+    );
+    tokio::spawn(PlanService::new(app_state.mongo_client.clone()).run_retention_pruner());
+
     let _web_cors = CorsLayer::new()
         .allow_origin([
             "http://localhost:5172".parse().unwrap(),
@@ -58,6 +87,7 @@ async fn main() {
             Method::GET,
             Method::POST,
             Method::PUT,
+            Method::PATCH,
             Method::DELETE,
             Method::OPTIONS,
         ]);
@@ -67,7 +97,13 @@ async fn main() {
         .nest("/users", user_endpoints())
         .nest("/auth", auth_endpoints())
         .nest("/spm", spm_endpoints())
-        .with_state(app_state)
+        .nest("/notifications", notification_endpoints())
+        .nest("/provisioning", provisioning_endpoints())
+        .nest("/invitations", invitation_endpoints())
+        .nest("/plans", plan_endpoints())
+        .with_state(app_state.clone())
+        // Lets the auth middleware look up the current user.
+        .layer(Extension(app_state))
         .layer(_web_cors)
         .layer(TraceLayer::new_for_http());
 
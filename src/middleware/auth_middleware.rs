@@ -1,15 +1,63 @@
-use axum::{extract::Request, http::header::AUTHORIZATION, middleware::Next, response::Response};
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, OriginalUri, Path, Request},
+    http::{header::AUTHORIZATION, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use subtle::ConstantTimeEq;
 
 use crate::{
-    models::user::AuthUserDto,
+    models::{
+        spm::{Cage, SpmDeviceToken},
+        user::{AuthUserDto, Permission, User},
+    },
+    repository::{spm_repository::SpmRepository, user_repository::UserRepository},
+    services::spm_service::find_accessible_cage,
     utils::{
-        error_handler::invalid_credentials_error,
+        error_handler::{access_denied_error, invalid_credentials_error, not_found_error},
+        helper::{hash_id_with_secret, sha256_hex, sign_device_request, unseal_device_token},
         jwt::{self, Claims},
         response::ApiErrorResponse,
     },
+    AppState,
 };
 
 pub async fn requires_auth(mut req: Request, next: Next) -> Result<Response, ApiErrorResponse> {
+    let mut current_user = authenticate(&req)?;
+    current_user.user_type = ensure_active_user(app_state(&req)?, &current_user.id)
+        .await?
+        .r#type;
+    req.extensions_mut().insert(current_user);
+    let res = next.run(req).await;
+    Ok(res)
+}
+
+/// Like `requires_auth`, but also refuses users whose role lacks `permission`.
+pub fn requires_permission(
+    permission: Permission,
+) -> impl Fn(Request, Next) -> BoxFuture<'static, Result<Response, ApiErrorResponse>> + Clone {
+    move |mut req: Request, next: Next| {
+        Box::pin(async move {
+            let mut current_user = authenticate(&req)?;
+            current_user.user_type = ensure_active_user(app_state(&req)?, &current_user.id)
+                .await?
+                .r#type;
+            if !current_user.has_permission(permission) {
+                return Err(access_denied_error(permission));
+            }
+            req.extensions_mut().insert(current_user);
+            Ok(next.run(req).await)
+        })
+    }
+}
+
+fn authenticate(req: &Request) -> Result<AuthUserDto, ApiErrorResponse> {
     let bearer_token = req
         .headers()
         .get(AUTHORIZATION)
@@ -23,16 +71,92 @@ pub async fn requires_auth(mut req: Request, next: Next) -> Result<Response, Api
 
     let claims: Claims =
         jwt::verify(token.to_string(), Some(true)).map_err(invalid_credentials_error)?;
-    let current_user = AuthUserDto {
+    Ok(AuthUserDto {
         id: claims.sub,
         user_type: claims.role,
+    })
+}
+
+fn app_state(req: &Request) -> Result<Arc<AppState>, ApiErrorResponse> {
+    req.extensions()
+        .get::<Arc<AppState>>()
+        .cloned()
+        .ok_or_else(|| ApiErrorResponse::new(500, String::from("Missing application state")))
+}
+
+/// Access tokens outlive changes to the account, so each request reloads the
+/// user, refusing disabled or deleted ones and checking permissions against
+/// the stored role.
+async fn ensure_active_user(
+    app_state: Arc<AppState>,
+    user_id: &str,
+) -> Result<User, ApiErrorResponse> {
+    let db = app_state.mongo_client.database("fiyadb");
+
+    match UserRepository::new(&db).find_user_by_id(user_id).await? {
+        Some(user) if user.disabled => Err(ApiErrorResponse::new(
+            403,
+            String::from("Account is disabled"),
+        )),
+        Some(user) => Ok(user),
+        None => Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
+    }
+}
+
+pub async fn requires_ws_auth(mut req: Request, next: Next) -> Result<Response, ApiErrorResponse> {
+    // Browsers can't set headers on a websocket upgrade, so fall back to the query string.
+    let bearer_token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(String::from)
+        .or_else(|| {
+            req.uri().query().and_then(|query| {
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("access_token="))
+                    .map(String::from)
+            })
+        });
+
+    let token = match bearer_token {
+        Some(token) => token,
+        None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
+    };
+
+    let claims: Claims = jwt::verify(token, Some(true)).map_err(invalid_credentials_error)?;
+    let user = ensure_active_user(app_state(&req)?, &claims.sub).await?;
+    let current_user = AuthUserDto {
+        id: claims.sub,
+        user_type: user.r#type,
     };
+    if !current_user.has_permission(Permission::CagesRead) {
+        return Err(access_denied_error(Permission::CagesRead));
+    }
     req.extensions_mut().insert(current_user);
     let res = next.run(req).await;
     Ok(res)
 }
 
+const TIMESTAMP_HEADER: &str = "x-fiya-timestamp";
+const NONCE_HEADER: &str = "x-fiya-nonce";
+const SIGNATURE_HEADER: &str = "x-fiya-signature";
+const MAX_SIGNED_REQUEST_SKEW_SECS: u64 = 300;
+const MAX_SIGNED_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Accepts either a bearer device token, or a signed request carrying a
+/// timestamp, a nonce and an HMAC of the request keyed by the device token.
+/// The signature itself is checked by `AuthenticatedDevice`, which has access
+/// to the stored token.
 pub async fn requires_spm_auth(mut req: Request, next: Next) -> Result<Response, ApiErrorResponse> {
+    if req.headers().contains_key(SIGNATURE_HEADER) {
+        let signed_device_request = read_signed_device_request(&mut req).await?;
+        req.extensions_mut()
+            .insert(SpmDeviceAuth::Signed(signed_device_request));
+        return Ok(next.run(req).await);
+    }
+
     let bearer_token = req
         .headers()
         .get(AUTHORIZATION)
@@ -44,7 +168,7 @@ pub async fn requires_spm_auth(mut req: Request, next: Next) -> Result<Response,
         None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
     };
 
-    let spm_device_auth = SpmDeviceAuth {
+    let spm_device_auth = SpmDeviceAuth::Bearer {
         token: token.to_string(),
     };
 
@@ -53,7 +177,203 @@ pub async fn requires_spm_auth(mut req: Request, next: Next) -> Result<Response,
     Ok(res)
 }
 
+async fn read_signed_device_request(
+    req: &mut Request,
+) -> Result<SignedDeviceRequest, ApiErrorResponse> {
+    let unauthorized = |message: &str| ApiErrorResponse::new(401, message.to_string());
+    let headers = req.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|header| header.to_str().ok())
+            .map(String::from)
+    };
+
+    let signature = header(SIGNATURE_HEADER).ok_or_else(|| unauthorized("Unauthorized"))?;
+    let timestamp: i64 = header(TIMESTAMP_HEADER)
+        .and_then(|timestamp| timestamp.parse().ok())
+        .ok_or_else(|| unauthorized("A valid request timestamp is required"))?;
+    if Utc::now().timestamp().abs_diff(timestamp) > MAX_SIGNED_REQUEST_SKEW_SECS {
+        return Err(unauthorized(
+            "Request timestamp is outside the allowed window",
+        ));
+    }
+    let nonce = header(NONCE_HEADER)
+        .filter(|nonce| (16..=128).contains(&nonce.len()))
+        .ok_or_else(|| unauthorized("A request nonce of 16 to 128 characters is required"))?;
+
+    // Nested routers only see the path below their prefix, but devices sign
+    // the full path they called.
+    let uri = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|original_uri| original_uri.0.clone())
+        .unwrap_or_else(|| req.uri().clone());
+    let path = uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+    let method = req.method().clone();
+
+    let body = std::mem::take(req.body_mut());
+    let body_bytes = to_bytes(body, MAX_SIGNED_BODY_BYTES)
+        .await
+        .map_err(|_| ApiErrorResponse::new(413, String::from("Request body is too large")))?;
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        timestamp,
+        nonce,
+        sha256_hex(&body_bytes)
+    );
+    *req.body_mut() = Body::from(body_bytes);
+
+    Ok(SignedDeviceRequest {
+        nonce,
+        signature: signature.to_ascii_lowercase(),
+        canonical_request,
+    })
+}
+
+#[derive(Clone)]
+pub enum SpmDeviceAuth {
+    Bearer { token: String },
+    Signed(SignedDeviceRequest),
+}
+
 #[derive(Clone)]
-pub struct SpmDeviceAuth {
-    pub token: String,
+pub struct SignedDeviceRequest {
+    pub nonce: String,
+    pub signature: String,
+    pub canonical_request: String,
+}
+
+/// A device whose token has been verified against the `cage_id` in the path.
+/// Taking this as a handler argument is what makes a route device-only: the
+/// handler never runs unless the check passes.
+pub struct AuthenticatedDevice {
+    pub cage: Cage,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthenticatedDevice {
+    type Rejection = ApiErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let unauthorized = || ApiErrorResponse::new(403, String::from("Unauthorized"));
+
+        // Fails closed when a route forgets the `requires_spm_auth` layer.
+        let spm_device_auth = parts
+            .extensions
+            .get::<SpmDeviceAuth>()
+            .cloned()
+            .ok_or_else(|| ApiErrorResponse::new(401, String::from("Unauthorized")))?;
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| unauthorized())?;
+        let cage_id = params.get("cage_id").ok_or_else(unauthorized)?;
+
+        let db = state.mongo_client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let spm_device_token = spm_repo
+            .find_device_token_by_id(cage_id)
+            .await?
+            .ok_or_else(unauthorized)?;
+        match spm_device_auth {
+            SpmDeviceAuth::Bearer { token } => {
+                let hashed_device_token = hash_id_with_secret(&token);
+                if !spm_device_token.accepts(&hashed_device_token, Utc::now()) {
+                    return Err(unauthorized());
+                }
+            }
+            SpmDeviceAuth::Signed(signed_device_request) => {
+                if !verify_signed_device_request(&spm_device_token, &signed_device_request) {
+                    return Err(unauthorized());
+                }
+                // Only remember nonces of genuine requests, so forged ones
+                // can't burn nonces the device has yet to use.
+                if !spm_repo
+                    .record_device_nonce(cage_id, &signed_device_request.nonce)
+                    .await?
+                {
+                    return Err(ApiErrorResponse::new(
+                        401,
+                        String::from("Request has already been used"),
+                    ));
+                }
+            }
+        }
+
+        let cage = spm_repo
+            .find_cage_by_cage_id(cage_id)
+            .await?
+            .ok_or_else(unauthorized)?;
+        if cage.archived_at.is_some() {
+            return Err(ApiErrorResponse::new(
+                410,
+                String::from("Cage has been archived"),
+            ));
+        }
+        Ok(AuthenticatedDevice { cage })
+    }
+}
+
+/// A cage the authenticated user may manage, resolved from the `cage_id` in
+/// the path. Like [`AuthenticatedDevice`], taking this as a handler argument
+/// is what enforces the check.
+pub struct AuthorizedCage {
+    pub cage: Cage,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthorizedCage {
+    type Rejection = ApiErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        // Fails closed when a route forgets the `requires_auth` layer.
+        let auth_user = parts
+            .extensions
+            .get::<AuthUserDto>()
+            .cloned()
+            .ok_or_else(|| ApiErrorResponse::new(401, String::from("Unauthorized")))?;
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| not_found_error((), "Cage does not exist"))?;
+        let cage_id = params
+            .get("cage_id")
+            .ok_or_else(|| not_found_error((), "Cage does not exist"))?;
+
+        let db = state.mongo_client.database("fiyadb");
+        let cage = find_accessible_cage(&db, &auth_user.id, cage_id).await?;
+        Ok(AuthorizedCage { cage })
+    }
+}
+
+fn verify_signed_device_request(
+    spm_device_token: &SpmDeviceToken,
+    signed_device_request: &SignedDeviceRequest,
+) -> bool {
+    spm_device_token
+        .usable_versions(Utc::now())
+        .filter_map(|version| {
+            let seal_context = SpmDeviceToken::seal_context(&spm_device_token.id, version.version);
+            unseal_device_token(version.sealed_token.as_deref()?, &seal_context)
+        })
+        .any(|device_token| {
+            let expected_signature =
+                sign_device_request(&device_token, &signed_device_request.canonical_request);
+            bool::from(
+                expected_signature
+                    .as_bytes()
+                    .ct_eq(signed_device_request.signature.as_bytes()),
+            )
+        })
 }
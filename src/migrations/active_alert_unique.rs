@@ -0,0 +1,67 @@
+use bson::{doc, Document};
+use futures::TryStreamExt;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::utils::{error_handler::internal_error, response::ApiErrorResponse};
+
+pub const NAME: &str = "0014_active_alert_unique";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let alerts = db.collection::<Document>("alerts");
+
+    // Keep the earliest of any duplicate active alerts; the rest were opened
+    // by racing readings and are retired so the unique index can be built.
+    let duplicates: Vec<Document> = alerts
+        .aggregate(vec![
+            doc! { "$match": { "active": true } },
+            doc! { "$sort": { "opened_at": 1 } },
+            doc! { "$group": {
+                "_id": { "cage_id": "$cage_id", "metric": "$metric" },
+                "ids": { "$push": "$_id" },
+                "count": { "$sum": 1 },
+            } },
+            doc! { "$match": { "count": { "$gt": 1 } } },
+        ])
+        .await
+        .map_err(internal_error)?
+        .try_collect()
+        .await
+        .map_err(internal_error)?;
+
+    for duplicate in duplicates {
+        let ids = duplicate.get_array("ids").map_err(internal_error)?;
+        let extra_ids: Vec<_> = ids.iter().skip(1).cloned().collect();
+        alerts
+            .update_many(
+                doc! { "_id": { "$in": extra_ids } },
+                vec![doc! {
+                    "$set": {
+                        "active": false,
+                        "state": "resolved",
+                        "cleared_at": "$$NOW",
+                        "resolved_at": "$$NOW",
+                        "updated_at": "$$NOW",
+                    },
+                }],
+            )
+            .await
+            .map_err(internal_error)?;
+    }
+
+    let index_model = IndexModel::builder()
+        .keys(doc! { "cage_id": 1, "metric": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(String::from("cage_id_1_metric_1_active_unique"))
+                .unique(true)
+                .partial_filter_expression(doc! { "active": true })
+                .build(),
+        )
+        .build();
+    alerts
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use bson::{doc, Document};
+use mongodb::{error::ErrorKind, Database, IndexModel};
+
+use crate::utils::{error_handler::internal_error, response::ApiErrorResponse};
+
+pub const NAME: &str = "0004_alert_lifecycle";
+
+const INDEX_NOT_FOUND: i32 = 27;
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let alerts = db.collection::<Document>("alerts");
+
+    alerts
+        .update_many(
+            doc! { "status": "open" },
+            vec![
+                doc! {
+                    "$set": {
+                        "state": "open",
+                        "active": true,
+                        "severity": "warning",
+                        "comments": [],
+                    },
+                },
+                doc! { "$unset": ["status", "closed_at"] },
+            ],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    alerts
+        .update_many(
+            doc! { "status": "closed" },
+            vec![
+                doc! {
+                    "$set": {
+                        "state": "resolved",
+                        "active": false,
+                        "severity": "warning",
+                        "comments": [],
+                        "cleared_at": "$closed_at",
+                        "resolved_at": "$closed_at",
+                    },
+                },
+                doc! { "$unset": ["status", "closed_at"] },
+            ],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    // The engine now looks alerts up by `active` instead of `status`. A re-run
+    // after a partial apply finds the index already gone.
+    match alerts.drop_index("cage_id_1_metric_1_status_1").await {
+        Err(err) if !is_index_not_found(&err) => return Err(internal_error(err)),
+        _ => {}
+    }
+
+    let index_models = vec![
+        IndexModel::builder()
+            .keys(doc! { "cage_id": 1, "metric": 1, "active": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "cage_id": 1, "opened_at": -1 })
+            .build(),
+    ];
+    alerts
+        .create_indexes(index_models)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
+
+fn is_index_not_found(err: &mongodb::error::Error) -> bool {
+    matches!(
+        *err.kind,
+        ErrorKind::Command(ref command_error) if command_error.code == INDEX_NOT_FOUND
+    )
+}
@@ -0,0 +1,25 @@
+use bson::doc;
+use mongodb::{Database, IndexModel};
+
+use crate::{
+    models::spm::CageReading,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0006_cage_readings_latest_index";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let cage_readings = db.collection::<CageReading>("cage_readings");
+
+    // Lets the latest-reading lookup walk each cage's readings newest first
+    // and stop at the first one.
+    let index_model = IndexModel::builder()
+        .keys(doc! { "cage_id": 1, "timestamp": -1 })
+        .build();
+    cage_readings
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use bson::doc;
+use mongodb::{Database, IndexModel};
+
+use crate::{
+    models::alert::Alert,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0002_create_alert_indexes";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let alerts = db.collection::<Alert>("alerts");
+
+    let index_models = vec![
+        IndexModel::builder()
+            .keys(doc! { "cage_id": 1, "metric": 1, "status": 1 })
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "assigned_monitor": 1, "opened_at": -1 })
+            .build(),
+    ];
+    alerts
+        .create_indexes(index_models)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::provisioning::DeviceClaim,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0010_device_claims";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let device_claims = db.collection::<DeviceClaim>("device_claims");
+
+    let index_models = vec![
+        IndexModel::builder()
+            .keys(doc! { "claim_code_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "serial_number": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+    ];
+    device_claims
+        .create_indexes(index_models)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
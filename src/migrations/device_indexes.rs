@@ -0,0 +1,23 @@
+use bson::doc;
+use mongodb::{Database, IndexModel};
+
+use crate::{
+    models::spm::SpmDevice,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0007_device_indexes";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let devices = db.collection::<SpmDevice>("devices");
+
+    let index_model = IndexModel::builder()
+        .keys(doc! { "online": 1, "last_seen_at": 1 })
+        .build();
+    devices
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::spm::DeviceNonce,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0009_device_nonces";
+
+// Comfortably longer than the window in which a signed request's timestamp
+// is accepted, so a nonce can't expire while it could still be replayed.
+const NONCE_TTL: Duration = Duration::from_secs(30 * 60);
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let device_nonces = db.collection::<DeviceNonce>("device_nonces");
+
+    let index_model = IndexModel::builder()
+        .keys(doc! { "created_at": 1 })
+        .options(IndexOptions::builder().expire_after(NONCE_TTL).build())
+        .build();
+    device_nonces
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use bson::{doc, Document};
+use mongodb::Database;
+
+use crate::utils::{error_handler::internal_error, response::ApiErrorResponse};
+
+pub const NAME: &str = "0008_device_token_versions";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let device_tokens = db.collection::<Document>("device_token");
+
+    // Existing tokens become the first version of each cage's history.
+    let filter = doc! { "versions": { "$exists": false } };
+    let update = vec![doc! { "$set": {
+        "version": 1_i64,
+        "versions": [{
+            "version": 1_i64,
+            "token": "$token",
+            "created_at": "$created_at",
+        }],
+    } }];
+    device_tokens
+        .update_many(filter, update)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
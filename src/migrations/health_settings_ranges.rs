@@ -0,0 +1,40 @@
+use bson::{doc, Document};
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::utils::{error_handler::internal_error, response::ApiErrorResponse};
+
+pub const NAME: &str = "0003_health_settings_ranges";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let health_settings = db.collection::<Document>("health_settings");
+
+    // Single values were only ever compared as upper limits, so they become
+    // the upper bound of the new range.
+    for metric in ["temperature", "pressure", "humidity"] {
+        let field = format!("${metric}");
+        let filter = doc! { metric: { "$type": "number" } };
+        let update = vec![doc! { "$set": { metric: {
+            "lower": null,
+            "upper": field,
+            "hysteresis": 0.0,
+            "min_duration_secs": 0_i64,
+        } } }];
+
+        health_settings
+            .update_many(filter, update)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    let sensor_breaches = db.collection::<Document>("sensor_breaches");
+    let index_model = IndexModel::builder()
+        .keys(doc! { "cage_id": 1, "metric": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    sensor_breaches
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
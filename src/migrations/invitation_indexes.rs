@@ -0,0 +1,29 @@
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::invitation::Invitation,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0012_invitation_indexes";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let invitations = db.collection::<Invitation>("invitations");
+
+    let index_models = vec![
+        IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "invited_by": 1, "created_at": -1 })
+            .build(),
+    ];
+    invitations
+        .create_indexes(index_models)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
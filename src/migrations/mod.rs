@@ -0,0 +1,148 @@
+use std::future::Future;
+
+use bson::doc;
+use chrono::{DateTime, Utc};
+use mongodb::{Client, Collection};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{error_handler::internal_error, response::ApiErrorResponse};
+
+pub mod active_alert_unique;
+pub mod alert_lifecycle;
+pub mod cage_readings_latest_index;
+pub mod create_alert_indexes;
+pub mod device_claims;
+pub mod device_indexes;
+pub mod device_nonces;
+pub mod device_token_versions;
+pub mod health_settings_ranges;
+pub mod invitation_indexes;
+pub mod notification_indexes;
+pub mod plan_indexes;
+pub mod provisioning_sheets;
+pub mod reseal_device_tokens;
+pub mod sensitive_deliveries;
+pub mod sensitive_invitation_deliveries;
+pub mod split_cage_readings;
+
+#[derive(Serialize, Deserialize)]
+struct AppliedMigration {
+    #[serde(rename = "_id")]
+    name: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    applied_at: DateTime<Utc>,
+}
+
+pub async fn run_migrations(client: &Client) -> Result<(), ApiErrorResponse> {
+    let db = client.database("fiyadb");
+    let migrations = db.collection::<AppliedMigration>("migrations");
+
+    apply(
+        &migrations,
+        split_cage_readings::NAME,
+        split_cage_readings::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        create_alert_indexes::NAME,
+        create_alert_indexes::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        health_settings_ranges::NAME,
+        health_settings_ranges::up(&db),
+    )
+    .await?;
+    apply(&migrations, alert_lifecycle::NAME, alert_lifecycle::up(&db)).await?;
+    apply(
+        &migrations,
+        notification_indexes::NAME,
+        notification_indexes::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        cage_readings_latest_index::NAME,
+        cage_readings_latest_index::up(&db),
+    )
+    .await?;
+    apply(&migrations, device_indexes::NAME, device_indexes::up(&db)).await?;
+    apply(
+        &migrations,
+        device_token_versions::NAME,
+        device_token_versions::up(&db),
+    )
+    .await?;
+    apply(&migrations, device_nonces::NAME, device_nonces::up(&db)).await?;
+    apply(&migrations, device_claims::NAME, device_claims::up(&db)).await?;
+    apply(
+        &migrations,
+        provisioning_sheets::NAME,
+        provisioning_sheets::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        invitation_indexes::NAME,
+        invitation_indexes::up(&db),
+    )
+    .await?;
+    apply(&migrations, plan_indexes::NAME, plan_indexes::up(&db)).await?;
+    apply(
+        &migrations,
+        active_alert_unique::NAME,
+        active_alert_unique::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        sensitive_deliveries::NAME,
+        sensitive_deliveries::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        reseal_device_tokens::NAME,
+        reseal_device_tokens::up(&db),
+    )
+    .await?;
+    apply(
+        &migrations,
+        sensitive_invitation_deliveries::NAME,
+        sensitive_invitation_deliveries::up(&db),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn apply<F>(
+    migrations: &Collection<AppliedMigration>,
+    name: &str,
+    migration: F,
+) -> Result<(), ApiErrorResponse>
+where
+    F: Future<Output = Result<(), ApiErrorResponse>>,
+{
+    let applied = migrations
+        .find_one(doc! { "_id": name })
+        .await
+        .map_err(internal_error)?;
+    if applied.is_some() {
+        return Ok(());
+    }
+
+    tracing::info!("applying migration {name}");
+    migration.await?;
+
+    migrations
+        .insert_one(AppliedMigration {
+            name: name.to_string(),
+            applied_at: Utc::now(),
+        })
+        .await
+        .map_err(internal_error)?;
+    Ok(())
+}
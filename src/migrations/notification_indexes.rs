@@ -0,0 +1,29 @@
+use bson::doc;
+use mongodb::{Database, IndexModel};
+
+use crate::{
+    models::notification::{NotificationChannel, NotificationDelivery},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0005_notification_indexes";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let channels = db.collection::<NotificationChannel>("notification_channels");
+    channels
+        .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+        .await
+        .map_err(internal_error)?;
+
+    let deliveries = db.collection::<NotificationDelivery>("notification_deliveries");
+    deliveries
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "status": 1, "next_attempt_at": 1 })
+                .build(),
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
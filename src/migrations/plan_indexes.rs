@@ -0,0 +1,24 @@
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::plan::Plan,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0013_plan_indexes";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let plans = db.collection::<Plan>("plans");
+
+    let index_model = IndexModel::builder()
+        .keys(doc! { "admin_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    plans
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::provisioning::ProvisioningSheet,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0011_provisioning_sheets";
+
+// A sheet that isn't downloaded shortly after the cages were created is
+// dropped; the tokens can still be recovered by rotating them.
+const SHEET_TTL: Duration = Duration::from_secs(60 * 60);
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let provisioning_sheets = db.collection::<ProvisioningSheet>("provisioning_sheets");
+
+    let index_model = IndexModel::builder()
+        .keys(doc! { "created_at": 1 })
+        .options(IndexOptions::builder().expire_after(SHEET_TTL).build())
+        .build();
+    provisioning_sheets
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::env;
+
+use bson::doc;
+use dotenvy::dotenv;
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use mongodb::Database;
+use sha2::Sha256;
+
+use crate::{
+    models::spm::SpmDeviceToken,
+    utils::{
+        error_handler::internal_error,
+        helper::{seal_device_token, SEALED_TOKEN_PREFIX},
+        response::ApiErrorResponse,
+    },
+};
+
+pub const NAME: &str = "0016_reseal_device_tokens";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let device_tokens = db.collection::<SpmDeviceToken>("device_token");
+
+    let mut cursor = device_tokens
+        .find(doc! { "versions.sealed_token": { "$exists": true } })
+        .await
+        .map_err(internal_error)?;
+    while let Some(mut device_token) = cursor.try_next().await.map_err(internal_error)? {
+        let mut resealed = false;
+        for version in &mut device_token.versions {
+            let Some(sealed_token) = version.sealed_token.as_deref() else {
+                continue;
+            };
+            if sealed_token.starts_with(SEALED_TOKEN_PREFIX) {
+                continue;
+            }
+
+            // A token that can't be recovered just loses signed request
+            // support until it is rotated, the same as pre-signing tokens.
+            let context = SpmDeviceToken::seal_context(&device_token.id, version.version);
+            version.sealed_token = unseal_legacy_device_token(sealed_token, &context)
+                .map(|token| seal_device_token(&token, &context));
+            resealed = true;
+        }
+
+        if resealed {
+            let versions = bson::to_bson(&device_token.versions).map_err(internal_error)?;
+            device_tokens
+                .update_one(
+                    doc! { "_id": &device_token.id },
+                    doc! { "$set": { "versions": versions } },
+                )
+                .await
+                .map_err(internal_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reverses the HMAC keystream tokens used to be sealed with.
+fn unseal_legacy_device_token(sealed_token: &str, context: &str) -> Option<String> {
+    dotenv().ok();
+    let spm_secret = env::var("SPM_SECRET").expect("SPM_SECRET must be set");
+    let sealed_bytes = hex::decode(sealed_token).ok()?;
+
+    let mut device_token = Vec::with_capacity(sealed_bytes.len());
+    for (block_index, chunk) in sealed_bytes.chunks(32).enumerate() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(spm_secret.as_bytes())
+            .expect("Hmac can only accept secrets of a particular length");
+        mac.update(format!("device-token-seal:{context}:{block_index}").as_bytes());
+        let keystream = mac.finalize().into_bytes();
+        device_token.extend(
+            chunk
+                .iter()
+                .zip(keystream.iter())
+                .map(|(byte, key)| byte ^ key),
+        );
+    }
+    String::from_utf8(device_token).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::helper::{tests::set_test_spm_secret, unseal_device_token};
+
+    #[test]
+    fn recovers_legacy_sealed_tokens() {
+        set_test_spm_secret();
+        let context = SpmDeviceToken::seal_context("cage-1", 1);
+        // Sealed by the keystream scheme with SPM_SECRET "test-spm-secret".
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"test-spm-secret").unwrap();
+        mac.update(format!("device-token-seal:{context}:0").as_bytes());
+        let keystream = mac.finalize().into_bytes();
+        let legacy_sealed_token = hex::encode(
+            b"device-token"
+                .iter()
+                .zip(keystream.iter())
+                .map(|(byte, key)| byte ^ key)
+                .collect::<Vec<u8>>(),
+        );
+
+        let device_token = unseal_legacy_device_token(&legacy_sealed_token, &context).unwrap();
+        assert_eq!(device_token, "device-token");
+        let resealed_token = seal_device_token(&device_token, &context);
+        assert_eq!(
+            unseal_device_token(&resealed_token, &context).as_deref(),
+            Some("device-token")
+        );
+    }
+}
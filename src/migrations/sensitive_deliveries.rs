@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use bson::doc;
+use mongodb::{options::IndexOptions, Database, IndexModel};
+
+use crate::{
+    models::notification::{NotificationDelivery, REDACTED_BODY},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0015_sensitive_deliveries";
+
+/// Sensitive deliveries are removed after a day, well past the last retry.
+const SENSITIVE_DELIVERY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let deliveries = db.collection::<NotificationDelivery>("notification_deliveries");
+
+    // "user.created" emails used to carry a generated password in plain text.
+    deliveries
+        .update_many(
+            doc! { "event": "user.created" },
+            doc! { "$set": { "body": REDACTED_BODY, "sensitive": true } },
+        )
+        .await
+        .map_err(internal_error)?;
+
+    deliveries
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "created_at": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name(String::from("sensitive_created_at_ttl"))
+                        .expire_after(SENSITIVE_DELIVERY_TTL)
+                        .partial_filter_expression(doc! { "sensitive": true })
+                        .build(),
+                )
+                .build(),
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use bson::doc;
+use mongodb::Database;
+
+use crate::{
+    models::notification::{NotificationDelivery, REDACTED_BODY},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0017_sensitive_invitation_deliveries";
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let deliveries = db.collection::<NotificationDelivery>("notification_deliveries");
+
+    // Invitation emails carry a live invite code. Finished ones no longer need
+    // it; pending ones keep it for the retry and are redacted once they finish.
+    deliveries
+        .update_many(
+            doc! { "event": "user.invited", "status": { "$ne": "pending" } },
+            doc! { "$set": { "body": REDACTED_BODY, "sensitive": true } },
+        )
+        .await
+        .map_err(internal_error)?;
+    deliveries
+        .update_many(
+            doc! { "event": "user.invited", "status": "pending" },
+            doc! { "$set": { "sensitive": true } },
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use bson::{doc, oid::ObjectId};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    options::{IndexOptions, TimeseriesGranularity, TimeseriesOptions},
+    Database, IndexModel,
+};
+use serde::Deserialize;
+
+use crate::{
+    models::spm::{Cage, CageReading, ObjectRecognition},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub const NAME: &str = "0001_split_cage_readings";
+
+const INSERT_BATCH_SIZE: usize = 1000;
+
+#[derive(Deserialize)]
+struct LegacyCage {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    cage_id: String,
+    temperature: f32,
+    humidity: f32,
+    pressure: f32,
+    ammonia: f32,
+    co2: f32,
+    object_recognition: ObjectRecognition,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    timestamp: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct LegacyCageSummary {
+    #[serde(rename = "_id")]
+    cage_id: String,
+    first_id: ObjectId,
+    assigned_monitor: String,
+    livestock_no: u32,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn up(db: &Database) -> Result<(), ApiErrorResponse> {
+    let collection_names = db.list_collection_names().await.map_err(internal_error)?;
+
+    if !collection_names.iter().any(|name| name == "cage_readings") {
+        let timeseries = TimeseriesOptions::builder()
+            .time_field(String::from("timestamp"))
+            .meta_field(Some(String::from("cage_id")))
+            .granularity(Some(TimeseriesGranularity::Minutes))
+            .build();
+        db.create_collection("cage_readings")
+            .timeseries(timeseries)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    let cages = db.collection::<Cage>("cages");
+    let index_model = IndexModel::builder()
+        .keys(doc! { "cage_id": 1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    cages
+        .create_index(index_model)
+        .await
+        .map_err(internal_error)?;
+
+    if !collection_names.iter().any(|name| name == "cage") {
+        return Ok(());
+    }
+
+    let legacy_cages = db.collection::<LegacyCage>("cage");
+    let cage_readings = db.collection::<CageReading>("cage_readings");
+
+    // The registry keeps the newest metadata for each cage, while the oldest row
+    // is the placeholder `add_new_cage` used to write and is not a real reading.
+    let pipeline = vec![
+        doc! { "$sort": { "created_at": 1 } },
+        doc! { "$group": {
+            "_id": "$cage_id",
+            "first_id": { "$first": "$_id" },
+            "created_at": { "$first": "$created_at" },
+            "assigned_monitor": { "$last": "$assigned_monitor" },
+            "livestock_no": { "$last": "$livestock_no" },
+            "updated_at": { "$last": "$updated_at" },
+        } },
+    ];
+    let summaries: Vec<LegacyCageSummary> = legacy_cages
+        .aggregate(pipeline)
+        .with_type::<LegacyCageSummary>()
+        .await
+        .map_err(internal_error)?
+        .try_collect()
+        .await
+        .map_err(internal_error)?;
+
+    let mut placeholder_ids = HashSet::new();
+    for summary in summaries {
+        placeholder_ids.insert(summary.first_id);
+        let cage = Cage {
+            id: summary.first_id,
+            cage_id: summary.cage_id,
+            assigned_monitor: summary.assigned_monitor,
+            livestock_no: summary.livestock_no,
+            archived_at: None,
+            created_at: summary.created_at,
+            updated_at: summary.updated_at,
+        };
+        cages
+            .replace_one(doc! { "cage_id": &cage.cage_id }, &cage)
+            .upsert(true)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    // Nothing else writes readings until migrations finish, so anything here
+    // is left over from an interrupted run and would otherwise be duplicated.
+    cage_readings
+        .delete_many(doc! {})
+        .await
+        .map_err(internal_error)?;
+
+    let mut cursor = legacy_cages.find(doc! {}).await.map_err(internal_error)?;
+    let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+    while let Some(legacy_cage) = cursor.try_next().await.map_err(internal_error)? {
+        if placeholder_ids.contains(&legacy_cage.id) {
+            continue;
+        }
+
+        batch.push(CageReading {
+            id: legacy_cage.id,
+            cage_id: legacy_cage.cage_id,
+            temperature: legacy_cage.temperature,
+            humidity: legacy_cage.humidity,
+            pressure: legacy_cage.pressure,
+            ammonia: legacy_cage.ammonia,
+            co2: legacy_cage.co2,
+            object_recognition: legacy_cage.object_recognition,
+            timestamp: legacy_cage.timestamp,
+            created_at: legacy_cage.created_at,
+        });
+
+        if batch.len() == INSERT_BATCH_SIZE {
+            cage_readings
+                .insert_many(&batch)
+                .await
+                .map_err(internal_error)?;
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        cage_readings
+            .insert_many(&batch)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    Ok(())
+}
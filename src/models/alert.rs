@@ -0,0 +1,99 @@
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AlertMetric {
+    Temperature,
+    Pressure,
+    Humidity,
+    Ammonia,
+    Co2,
+    Coccidiosis,
+    Newcastle,
+    Salmonella,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AlertState {
+    Open,
+    Acknowledged,
+    Resolved,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AlertSeverity {
+    Warning,
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertComment {
+    pub author_id: String,
+    pub body: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub cage_id: String,
+    pub assigned_monitor: String,
+    pub metric: AlertMetric,
+    pub severity: AlertSeverity,
+    pub state: AlertState,
+    /// Whether the underlying condition is still present. The engine clears
+    /// this on its own, independently of how people triage the alert.
+    pub active: bool,
+    pub threshold: f32,
+    pub trigger_value: f32,
+    pub last_value: f32,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub opened_at: DateTime<Utc>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub cleared_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acknowledged_by: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub resolved_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub comments: Vec<AlertComment>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensorBreach {
+    pub cage_id: String,
+    pub metric: AlertMetric,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub started_at: DateTime<Utc>,
+    /// Number of consecutive out-of-range readings seen so far.
+    #[serde(default)]
+    pub readings: u32,
+}
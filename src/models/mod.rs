@@ -1,3 +1,8 @@
+pub mod alert;
+pub mod invitation;
+pub mod notification;
+pub mod plan;
+pub mod provisioning;
 pub mod refresh_token;
 pub mod spm;
 pub mod user;
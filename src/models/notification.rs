@@ -0,0 +1,70 @@
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChannelKind {
+    Webhook,
+    Email,
+    Sms,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationChannel {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: String,
+    pub kind: ChannelKind,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub enabled: bool,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Stored in place of the body of a sensitive delivery once it is no longer
+/// needed for a retry.
+pub const REDACTED_BODY: &str = "[redacted]";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationDelivery {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: Option<String>,
+    pub kind: ChannelKind,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub event: String,
+    pub subject: String,
+    pub body: String,
+    /// The body carries a secret: it is redacted once the delivery settles and
+    /// the whole delivery expires after a day regardless.
+    #[serde(default)]
+    pub sensitive: bool,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
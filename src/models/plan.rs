@@ -0,0 +1,49 @@
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Pdf,
+}
+
+/// Limits for an admin account. Customers created by the admin, and their
+/// cages, count against the admin's plan.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Plan {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub admin_id: ObjectId,
+    pub name: String,
+    pub max_customers: u32,
+    pub max_cages: u32,
+    /// Readings older than this are pruned.
+    pub retention_days: u32,
+    pub export_formats: Vec<ExportFormat>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Plan {
+    /// The plan for accounts that have never been assigned one.
+    pub fn default_for(admin_id: ObjectId) -> Self {
+        let now = Utc::now();
+        Plan {
+            id: ObjectId::new(),
+            admin_id,
+            name: String::from("standard"),
+            max_customers: 5,
+            max_cages: 100,
+            retention_days: 365,
+            export_formats: vec![ExportFormat::Csv, ExportFormat::Pdf],
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ClaimStatus {
+    /// Registered by an admin, waiting for a user to claim it.
+    Unclaimed,
+    /// Claimed by a user, waiting for the device to collect its token.
+    Claimed,
+    /// The device has collected its token.
+    Delivered,
+}
+
+/// A pre-registered device and the claim code printed on it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceClaim {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub serial_number: String,
+    /// Hash of the claim code, so a database leak doesn't leak the codes.
+    pub claim_code_hash: String,
+    pub status: ClaimStatus,
+    pub registered_by: String,
+    #[serde(default)]
+    pub cage_id: Option<String>,
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub claimed_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub delivered_at: Option<DateTime<Utc>>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A provisioning sheet waiting to be downloaded. It only references the
+/// cages; their tokens are unsealed when the sheet is rendered, and the
+/// record is deleted on first download.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProvisioningSheet {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub created_by: String,
+    pub cage_ids: Vec<String>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
@@ -1,6 +1,10 @@
+use std::{fmt, str::FromStr};
+
 use bson::oid::ObjectId;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+use subtle::ConstantTimeEq;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cage {
@@ -9,6 +13,25 @@ pub struct Cage {
     pub cage_id: String,
     pub assigned_monitor: String,
     pub livestock_no: u32,
+    /// Set while the cage is archived: its history is kept but its device's
+    /// readings are refused.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CageReading {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub cage_id: String,
     pub temperature: f32,
     pub humidity: f32,
     pub pressure: f32,
@@ -19,8 +42,6 @@ pub struct Cage {
     pub timestamp: DateTime<Utc>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
-    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
-    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,13 +52,6 @@ pub struct CageWithDeviceToken {
     pub device_token: String,
     pub assigned_monitor: String,
     pub livestock_no: u32,
-    pub temperature: f32,
-    pub humidity: f32,
-    pub pressure: f32,
-    pub ammonia: f32,
-    pub co2: f32,
-    pub object_recognition: ObjectRecognition,
-    pub timestamp: String,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -50,27 +64,130 @@ pub struct ObjectRecognition {
     pub healthy: f32,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct UpdateCage {
-    pub temperature: f32,
-    pub humidity: f32,
-    pub pressure: f32,
-    pub ammonia: f32,
-    pub co2: f32,
-    pub object_recognition: ObjectRecognition,
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpmDeviceToken {
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// Hash of the current token.
+    pub token: String,
+    #[serde(default = "default_token_version")]
+    pub version: u32,
+    /// Every token the cage has been issued, including the current one.
+    #[serde(default)]
+    pub versions: Vec<DeviceTokenVersion>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub revoked_at: Option<DateTime<Utc>>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
-    pub timestamp: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub updated_at: DateTime<Utc>,
 }
 
+impl SpmDeviceToken {
+    pub fn new(cage_id: String, hashed_token: String, sealed_token: String) -> Self {
+        let now = Utc::now();
+        SpmDeviceToken {
+            id: cage_id,
+            token: hashed_token.clone(),
+            version: default_token_version(),
+            versions: vec![DeviceTokenVersion {
+                version: default_token_version(),
+                token: hashed_token,
+                sealed_token: Some(sealed_token),
+                created_at: now,
+                expires_at: None,
+                revoked_at: None,
+            }],
+            revoked_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// The context a token version is sealed under, unique per cage and version.
+    pub fn seal_context(cage_id: &str, version: u32) -> String {
+        format!("{cage_id}:{version}")
+    }
+
+    /// Whether a hashed token may authenticate right now: either the current
+    /// token, or a rotated one still inside its grace period.
+    pub fn accepts(&self, hashed_token: &str, now: DateTime<Utc>) -> bool {
+        if self.revoked_at.is_some() {
+            return false;
+        }
+        if bool::from(self.token.as_bytes().ct_eq(hashed_token.as_bytes())) {
+            return true;
+        }
+        self.usable_versions(now)
+            .any(|version| bool::from(version.token.as_bytes().ct_eq(hashed_token.as_bytes())))
+    }
+
+    /// Token versions that may currently authenticate.
+    pub fn usable_versions(&self, now: DateTime<Utc>) -> impl Iterator<Item = &DeviceTokenVersion> {
+        let revoked = self.revoked_at.is_some();
+        let current_version = self.version;
+        self.versions.iter().filter(move |version| {
+            !revoked
+                && version.revoked_at.is_none()
+                && (version.version == current_version
+                    || version
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at > now))
+        })
+    }
+}
+
+fn default_token_version() -> u32 {
+    1
+}
+
 #[derive(Serialize, Deserialize, Clone)]
-pub struct SpmDeviceToken {
-    #[serde(rename = "_id")]
-    pub id: String,
+pub struct DeviceTokenVersion {
+    pub version: u32,
     pub token: String,
+    /// The token sealed under `SPM_SECRET`, needed to check signed requests.
+    /// Missing for tokens issued before request signing existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_token: Option<String>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
+    /// When a rotated token stops working. Unset for the current token.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Liveness of the SPM unit attached to a cage, keyed by cage id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpmDevice {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub online: bool,
+    #[serde(default)]
+    pub firmware_version: Option<String>,
+    #[serde(default)]
+    pub uptime_secs: Option<u64>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub last_seen_at: DateTime<Utc>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub offline_since: Option<DateTime<Utc>>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub updated_at: DateTime<Utc>,
 }
@@ -78,7 +195,212 @@ pub struct SpmDeviceToken {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct HealthSettings {
     pub cage_id: String,
-    pub temperature: f32,
-    pub pressure: f32,
-    pub humidity: f32,
+    #[serde(default)]
+    pub temperature: SensorRange,
+    #[serde(default)]
+    pub pressure: SensorRange,
+    #[serde(default)]
+    pub humidity: SensorRange,
+    #[serde(default)]
+    pub ammonia: SensorRange,
+    #[serde(default)]
+    pub co2: SensorRange,
+    #[serde(default)]
+    pub coccidiosis: DiseaseThreshold,
+    #[serde(default)]
+    pub newcastle: DiseaseThreshold,
+    #[serde(default)]
+    pub salmonella: DiseaseThreshold,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SensorRange {
+    pub lower: Option<f32>,
+    pub upper: Option<f32>,
+    #[serde(default)]
+    pub hysteresis: f32,
+    #[serde(default)]
+    pub min_duration_secs: u64,
+}
+
+impl SensorRange {
+    /// Returns the bound a value has crossed, if any.
+    pub fn breached_bound(&self, value: f32) -> Option<f32> {
+        match (self.lower, self.upper) {
+            (Some(lower), _) if value < lower => Some(lower),
+            (_, Some(upper)) if value > upper => Some(upper),
+            _ => None,
+        }
+    }
+
+    /// A breach only clears once the value is back inside the range by at
+    /// least the hysteresis band, so readings hovering on a bound don't flap.
+    pub fn is_cleared(&self, value: f32) -> bool {
+        let above_lower = self
+            .lower
+            .is_none_or(|lower| value >= lower + self.hysteresis);
+        let below_upper = self
+            .upper
+            .is_none_or(|upper| value <= upper - self.hysteresis);
+        above_lower && below_upper
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiseaseThreshold {
+    pub threshold: Option<f32>,
+    /// How many readings in a row must exceed the threshold before an alert
+    /// opens, so a single misclassified frame doesn't page anyone.
+    #[serde(default = "default_consecutive_readings")]
+    pub consecutive_readings: u32,
+}
+
+impl Default for DiseaseThreshold {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            consecutive_readings: default_consecutive_readings(),
+        }
+    }
+}
+
+impl DiseaseThreshold {
+    /// Returns the threshold a probability is above, if any.
+    pub fn exceeded_threshold(&self, probability: f32) -> Option<f32> {
+        self.threshold.filter(|threshold| probability > *threshold)
+    }
+}
+
+fn default_consecutive_readings() -> u32 {
+    1
+}
+
+/// Averaged recognition probabilities for one bucket of a disease risk trend.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiseaseRiskBucket {
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub bucket_start: DateTime<Utc>,
+    pub readings: u32,
+    pub coccidiosis: f32,
+    pub newcastle: f32,
+    pub salmonella: f32,
+    pub healthy: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, Display, EnumIter)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SensorMetric {
+    Temperature,
+    Humidity,
+    Pressure,
+    Ammonia,
+    Co2,
+}
+
+/// A time bucket written as a count and a unit, e.g. `5m`, `1h` or `1d`.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketSize {
+    pub unit: &'static str,
+    pub bin_size: u32,
+}
+
+impl BucketSize {
+    pub fn duration(&self) -> Duration {
+        let bin_size = self.bin_size as i64;
+        match self.unit {
+            "minute" => Duration::minutes(bin_size),
+            "hour" => Duration::hours(bin_size),
+            _ => Duration::days(bin_size),
+        }
+    }
+}
+
+impl fmt::Display for BucketSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = &self.unit[..1];
+        write!(f, "{}{}", self.bin_size, suffix)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseBucketSizeError;
+
+impl fmt::Display for ParseBucketSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid bucket size")
+    }
+}
+
+impl std::error::Error for ParseBucketSizeError {}
+
+impl FromStr for BucketSize {
+    type Err = ParseBucketSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bin_size, unit) = [("m", "minute"), ("h", "hour"), ("d", "day")]
+            .into_iter()
+            .find_map(|(suffix, unit)| s.strip_suffix(suffix).map(|bin_size| (bin_size, unit)))
+            .ok_or(ParseBucketSizeError)?;
+        let bin_size: u32 = bin_size.parse().map_err(|_| ParseBucketSizeError)?;
+        if bin_size == 0 {
+            return Err(ParseBucketSizeError);
+        }
+
+        Ok(BucketSize { unit, bin_size })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricStats {
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+}
+
+/// Summary statistics for the readings of one time bucket. Metrics that
+/// weren't requested are left out.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReadingBucket {
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub bucket_start: DateTime<Utc>,
+    pub count: u32,
+    pub temperature: Option<MetricStats>,
+    pub humidity: Option<MetricStats>,
+    pub pressure: Option<MetricStats>,
+    pub ammonia: Option<MetricStats>,
+    pub co2: Option<MetricStats>,
+}
+
+/// A signed request nonce, kept only long enough to reject replays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceNonce {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bucket_sizes() {
+        let bucket_size: BucketSize = "15m".parse().unwrap();
+        assert_eq!((bucket_size.bin_size, bucket_size.unit), (15, "minute"));
+        let bucket_size: BucketSize = "1d".parse().unwrap();
+        assert_eq!((bucket_size.bin_size, bucket_size.unit), (1, "day"));
+        assert_eq!(bucket_size.to_string(), "1d");
+    }
+
+    #[test]
+    fn rejects_invalid_bucket_sizes() {
+        for bucket_size in ["", "m", "0h", "-1h", "15", "15s", "15é", "é", "1hh"] {
+            assert!(
+                bucket_size.parse::<BucketSize>().is_err(),
+                "{bucket_size:?} should be rejected"
+            );
+        }
+    }
 }
@@ -1,13 +1,114 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString, VariantNames};
 
-#[derive(Serialize, Deserialize, Debug, EnumString, VariantNames, Display)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, EnumString, VariantNames, Display,
+)]
 #[strum(serialize_all = "snake_case")]
 pub enum UserType {
+    /// Runs the platform: manages admin accounts and their plans.
+    SuperAdmin,
     Admin,
     Customer,
+    Owner,
+    FarmManager,
+    Worker,
+    /// Read-only access to the farm's cages and reports.
+    Veterinarian,
+}
+
+impl UserType {
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+
+        match self {
+            UserType::SuperAdmin => &[
+                CagesRead,
+                CagesWrite,
+                CagesDelete,
+                SettingsWrite,
+                UsersManage,
+                ReportsExport,
+                DevicesManage,
+                DeviceTokensManage,
+                PlansManage,
+                AdminsManage,
+            ],
+            UserType::Admin | UserType::Owner => &[
+                CagesRead,
+                CagesWrite,
+                CagesDelete,
+                SettingsWrite,
+                UsersManage,
+                ReportsExport,
+                DevicesManage,
+                DeviceTokensManage,
+            ],
+            UserType::Customer | UserType::FarmManager => &[
+                CagesRead,
+                CagesWrite,
+                CagesDelete,
+                SettingsWrite,
+                ReportsExport,
+                DeviceTokensManage,
+            ],
+            UserType::Worker => &[CagesRead, CagesWrite],
+            UserType::Veterinarian => &[CagesRead, ReportsExport],
+        }
+    }
+
+    /// The roles a user of this type may give the users they create or
+    /// manage. Admins set up customers and farm owners; whoever runs a farm
+    /// adds its staff.
+    pub fn grantable_roles(&self) -> &'static [UserType] {
+        use UserType::*;
+
+        match self {
+            SuperAdmin | Admin => &[Customer, Owner, FarmManager, Worker, Veterinarian],
+            Owner => &[FarmManager, Worker, Veterinarian],
+            Customer | FarmManager | Worker | Veterinarian => &[],
+        }
+    }
+
+    /// Farm staff work on the cages of the user who added them.
+    pub fn is_staff(&self) -> bool {
+        matches!(
+            self,
+            UserType::FarmManager | UserType::Worker | UserType::Veterinarian
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum Permission {
+    #[strum(serialize = "cages:read")]
+    CagesRead,
+    #[strum(serialize = "cages:write")]
+    CagesWrite,
+    /// Deleting cages and handing them to another user.
+    #[strum(serialize = "cages:delete")]
+    CagesDelete,
+    #[strum(serialize = "settings:write")]
+    SettingsWrite,
+    #[strum(serialize = "users:manage")]
+    UsersManage,
+    #[strum(serialize = "reports:export")]
+    ReportsExport,
+    /// Registering devices for users to claim.
+    #[strum(serialize = "devices:manage")]
+    DevicesManage,
+    /// Viewing, rotating and revoking the device tokens of accessible cages.
+    #[strum(serialize = "device_tokens:manage")]
+    DeviceTokensManage,
+    #[strum(serialize = "plans:manage")]
+    PlansManage,
+    /// Creating admin accounts; reserved for super-admins.
+    #[strum(serialize = "admins:manage")]
+    AdminsManage,
 }
 
 #[derive(Clone)]
@@ -16,6 +117,14 @@ pub struct AuthUserDto {
     pub user_type: String,
 }
 
+impl AuthUserDto {
+    /// Unknown roles have no permissions.
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        UserType::from_str(&self.user_type)
+            .is_ok_and(|user_type| user_type.permissions().contains(&permission))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
     #[serde(rename = "_id")]
@@ -31,6 +140,9 @@ pub struct User {
     pub created_by: Option<ObjectId>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub spm_id: Option<String>,
+    /// Disabled users can't sign in, and their existing tokens are refused.
+    #[serde(default)]
+    pub disabled: bool,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
@@ -50,6 +162,75 @@ pub struct NewUser {
     pub created_by: Option<ObjectId>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub spm_id: Option<String>,
+    pub disabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+impl From<User> for NewUser {
+    fn from(user: User) -> Self {
+        NewUser {
+            id: user.id.to_string(),
+            name: user.name,
+            email: user.email,
+            phone_number: user.phone_number,
+            r#type: user.r#type,
+            created_customers: user.created_customers,
+            created_by: user.created_by,
+            spm_id: user.spm_id,
+            disabled: user.disabled,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_user(user_type: &str) -> AuthUserDto {
+        AuthUserDto {
+            id: ObjectId::new().to_string(),
+            user_type: user_type.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_account_holders_register_devices() {
+        assert!(auth_user("admin").has_permission(Permission::DevicesManage));
+        assert!(auth_user("owner").has_permission(Permission::DevicesManage));
+        assert!(!auth_user("customer").has_permission(Permission::DevicesManage));
+        assert!(!auth_user("farm_manager").has_permission(Permission::DevicesManage));
+        assert!(auth_user("customer").has_permission(Permission::DeviceTokensManage));
+    }
+
+    #[test]
+    fn workers_cannot_delete_or_reassign_cages() {
+        let worker = auth_user("worker");
+        assert!(worker.has_permission(Permission::CagesWrite));
+        assert!(!worker.has_permission(Permission::CagesDelete));
+        assert!(auth_user("customer").has_permission(Permission::CagesDelete));
+    }
+
+    #[test]
+    fn unknown_roles_have_no_permissions() {
+        assert!(!auth_user("intruder").has_permission(Permission::CagesRead));
+        assert!(!auth_user("Admin").has_permission(Permission::CagesRead));
+    }
+
+    #[test]
+    fn roles_are_granted_down_the_account() {
+        assert!(UserType::Admin.grantable_roles().contains(&UserType::Owner));
+        assert!(UserType::Owner
+            .grantable_roles()
+            .contains(&UserType::Worker));
+        assert!(!UserType::Owner.grantable_roles().contains(&UserType::Owner));
+        assert!(!UserType::Admin.grantable_roles().contains(&UserType::Admin));
+        assert!(!UserType::Admin
+            .grantable_roles()
+            .contains(&UserType::SuperAdmin));
+        assert!(UserType::Customer.grantable_roles().is_empty());
+        assert!(UserType::FarmManager.grantable_roles().is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use std::env;
+
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::models::notification::ChannelKind;
+
+pub mod sms;
+pub mod smtp;
+pub mod webhook;
+
+#[cfg(test)]
+mod stand_in;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: String,
+    pub subject: String,
+    pub body: String,
+    /// Whether the body carries a secret such as an invite code, in which case
+    /// the queued copy is redacted after delivery.
+    #[serde(skip)]
+    pub sensitive: bool,
+}
+
+pub struct Recipient<'a> {
+    pub address: &'a str,
+    pub secret: Option<&'a str>,
+}
+
+#[derive(Debug, Error)]
+pub enum NotifierError {
+    #[error("{0} notifications are not configured")]
+    NotConfigured(ChannelKind),
+
+    #[error("invalid recipient: {0}")]
+    InvalidRecipient(String),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Email(#[from] lettre::error::Error),
+
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(
+        &self,
+        recipient: &Recipient<'_>,
+        notification: &Notification,
+    ) -> Result<(), NotifierError>;
+}
+
+/// The notifiers available to the delivery worker, keyed by channel kind.
+/// Email and SMS are optional because they depend on external configuration.
+pub struct Notifiers {
+    webhook: webhook::WebhookNotifier,
+    email: Option<smtp::SmtpNotifier>,
+    sms: Option<sms::SmsGatewayNotifier>,
+}
+
+impl Notifiers {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let email = env::var("SMTP_HOST")
+            .ok()
+            .and_then(|host| match smtp::SmtpNotifier::from_env(&host) {
+                Ok(notifier) => Some(notifier),
+                Err(err) => {
+                    tracing::error!("smtp notifier disabled: {err}");
+                    None
+                }
+            });
+        let sms = env::var("SMS_GATEWAY_URL")
+            .ok()
+            .map(|url| sms::SmsGatewayNotifier::new(url, env::var("SMS_GATEWAY_TOKEN").ok()));
+
+        Self {
+            webhook: webhook::WebhookNotifier::new(),
+            email,
+            sms,
+        }
+    }
+
+    pub fn for_kind(&self, kind: ChannelKind) -> Result<&dyn Notifier, NotifierError> {
+        match kind {
+            ChannelKind::Webhook => Ok(&self.webhook),
+            ChannelKind::Email => self
+                .email
+                .as_ref()
+                .map(|notifier| notifier as &dyn Notifier)
+                .ok_or(NotifierError::NotConfigured(kind)),
+            ChannelKind::Sms => self
+                .sms
+                .as_ref()
+                .map(|notifier| notifier as &dyn Notifier)
+                .ok_or(NotifierError::NotConfigured(kind)),
+        }
+    }
+}
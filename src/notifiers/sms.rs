@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Serialize;
+
+use super::{Notification, Notifier, NotifierError, Recipient};
+
+const SMS_GATEWAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct SmsGatewayRequest<'a> {
+    to: &'a str,
+    message: String,
+}
+
+/// Sends messages through any HTTP gateway that accepts `{ "to", "message" }`
+/// as JSON, optionally authenticated with a bearer token.
+pub struct SmsGatewayNotifier {
+    http_client: Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl SmsGatewayNotifier {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        Self {
+            http_client: Client::new(),
+            url,
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SmsGatewayNotifier {
+    async fn send(
+        &self,
+        recipient: &Recipient<'_>,
+        notification: &Notification,
+    ) -> Result<(), NotifierError> {
+        let payload = SmsGatewayRequest {
+            to: recipient.address,
+            message: format!("{}: {}", notification.subject, notification.body),
+        };
+
+        let mut request = self
+            .http_client
+            .post(&self.url)
+            .timeout(SMS_GATEWAY_TIMEOUT)
+            .json(&payload);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::notifiers::stand_in::http_stand_in;
+
+    fn notification() -> Notification {
+        Notification {
+            event: String::from("device.offline"),
+            subject: String::from("Device on cage C-1 is offline"),
+            body: String::from("The device has not reported since 10:00."),
+            sensitive: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_message_with_bearer_token() {
+        let (url, mut received) = http_stand_in(StatusCode::OK).await;
+        let notifier = SmsGatewayNotifier::new(url, Some(String::from("gateway-token")));
+        let recipient = Recipient {
+            address: "+2348012345678",
+            secret: None,
+        };
+
+        notifier.send(&recipient, &notification()).await.unwrap();
+
+        let request = received.recv().await.unwrap();
+        assert_eq!(request.headers["authorization"], "Bearer gateway-token");
+        let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload["to"], "+2348012345678");
+        assert_eq!(
+            payload["message"],
+            "Device on cage C-1 is offline: The device has not reported since 10:00."
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_status() {
+        let (url, _received) = http_stand_in(StatusCode::BAD_GATEWAY).await;
+        let notifier = SmsGatewayNotifier::new(url, None);
+        let recipient = Recipient {
+            address: "+2348012345678",
+            secret: None,
+        };
+
+        let sent = notifier.send(&recipient, &notification()).await;
+        assert!(matches!(sent, Err(NotifierError::Http(_))));
+    }
+}
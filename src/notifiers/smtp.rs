@@ -0,0 +1,131 @@
+use std::env;
+
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::{Notification, Notifier, NotifierError, Recipient};
+
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpNotifier {
+    /// Reads `SMTP_PORT`, `SMTP_FROM`, `SMTP_USERNAME`, `SMTP_PASSWORD` and
+    /// `SMTP_TLS`. With `SMTP_TLS=false` the connection is plain text, which is
+    /// only meant for local mail sinks.
+    pub fn from_env(host: &str) -> Result<Self, NotifierError> {
+        let use_tls = env::var("SMTP_TLS").map_or(true, |tls| tls != "false");
+        let mut builder = if use_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+
+        if let Some(port) = env::var("SMTP_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+        {
+            builder = builder.port(port);
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD"))
+        {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from = env::var("SMTP_FROM")
+            .unwrap_or_else(|_| String::from("Fiya <no-reply@fiya.local>"))
+            .parse()
+            .map_err(|err: lettre::address::AddressError| {
+                NotifierError::InvalidRecipient(err.to_string())
+            })?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(
+        &self,
+        recipient: &Recipient<'_>,
+        notification: &Notification,
+    ) -> Result<(), NotifierError> {
+        let to: Mailbox =
+            recipient
+                .address
+                .parse()
+                .map_err(|err: lettre::address::AddressError| {
+                    NotifierError::InvalidRecipient(err.to_string())
+                })?;
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&notification.subject)
+            .body(notification.body.clone())?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifiers::stand_in::smtp_stand_in;
+
+    #[tokio::test]
+    async fn delivers_message_to_smtp_server() {
+        let (port, mut received) = smtp_stand_in().await;
+        let notifier = SmtpNotifier {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+                .port(port)
+                .build(),
+            from: "Fiya <no-reply@fiya.local>".parse().unwrap(),
+        };
+        let recipient = Recipient {
+            address: "farmer@example.com",
+            secret: None,
+        };
+        let notification = Notification {
+            event: String::from("alert.opened"),
+            subject: String::from("Critical temperature alert on cage C-1"),
+            body: String::from("Temperature crossed the threshold."),
+            sensitive: false,
+        };
+
+        notifier.send(&recipient, &notification).await.unwrap();
+
+        let message = received.recv().await.unwrap();
+        assert!(message.contains("To: farmer@example.com"));
+        assert!(message.contains("Subject: Critical temperature alert on cage C-1"));
+        assert!(message.contains("Temperature crossed the threshold."));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_address() {
+        let notifier = SmtpNotifier {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1").build(),
+            from: "Fiya <no-reply@fiya.local>".parse().unwrap(),
+        };
+        let recipient = Recipient {
+            address: "not-an-email",
+            secret: None,
+        };
+        let notification = Notification {
+            event: String::from("alert.opened"),
+            subject: String::from("subject"),
+            body: String::from("body"),
+            sensitive: false,
+        };
+
+        let sent = notifier.send(&recipient, &notification).await;
+        assert!(matches!(sent, Err(NotifierError::InvalidRecipient(_))));
+    }
+}
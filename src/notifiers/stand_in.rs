@@ -0,0 +1,87 @@
+//! Local stand-ins for the services the notifiers talk to.
+
+use axum::{
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+
+pub struct ReceivedRequest {
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// Accepts `POST /`, answering every request with `status`. Returns the URL to
+/// post to and the requests received so far.
+pub async fn http_stand_in(
+    status: StatusCode,
+) -> (String, mpsc::UnboundedReceiver<ReceivedRequest>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let app = Router::new().route(
+        "/",
+        post(move |headers: HeaderMap, body: String| {
+            let sender = sender.clone();
+            async move {
+                let _ = sender.send(ReceivedRequest { headers, body });
+                status
+            }
+        }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (format!("http://{address}/"), receiver)
+}
+
+/// Speaks just enough SMTP to accept one message. Returns the port to connect
+/// to and the DATA of each message received.
+pub async fn smtp_stand_in() -> (u16, mpsc::UnboundedReceiver<String>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 stand-in ESMTP\r\n").await.unwrap();
+
+        let mut data: Option<String> = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(message) = data.as_mut() {
+                if line == "." {
+                    let _ = sender.send(data.take().unwrap_or_default());
+                    writer.write_all(b"250 queued\r\n").await.unwrap();
+                } else {
+                    message.push_str(&line);
+                    message.push('\n');
+                }
+                continue;
+            }
+
+            let command = line.get(..4).unwrap_or_default().to_ascii_uppercase();
+            let reply: &[u8] = match command.as_str() {
+                "EHLO" | "HELO" => b"250 stand-in\r\n",
+                "DATA" => {
+                    data = Some(String::new());
+                    b"354 end data with <CR><LF>.<CR><LF>\r\n"
+                }
+                "QUIT" => {
+                    let _ = writer.write_all(b"221 bye\r\n").await;
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+    });
+
+    (port, receiver)
+}
@@ -0,0 +1,297 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Client, Url,
+};
+use sha2::Sha256;
+use tokio::net::lookup_host;
+
+use super::{Notification, Notifier, NotifierError, Recipient};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct WebhookNotifier {
+    http_client: Client,
+    allow_private_targets: bool,
+}
+
+impl WebhookNotifier {
+    /// Only delivers to public addresses: every resolved address is checked
+    /// at connect time and redirects aren't followed, so a webhook can't be
+    /// pointed at internal services.
+    pub fn new() -> Self {
+        let http_client = Client::builder()
+            .dns_resolver(Arc::new(PublicAddrResolver))
+            .redirect(Policy::none())
+            .build()
+            .expect("webhook http client should build");
+
+        Self {
+            http_client,
+            allow_private_targets: false,
+        }
+    }
+
+    /// For tests against local stand-ins only.
+    #[cfg(test)]
+    fn allowing_private_targets() -> Self {
+        Self {
+            http_client: Client::new(),
+            allow_private_targets: true,
+        }
+    }
+}
+
+impl Default for WebhookNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fails unless `address` is an http(s) URL whose host only resolves to
+/// public addresses.
+pub async fn ensure_public_webhook_url(address: &str) -> Result<(), NotifierError> {
+    let url =
+        Url::parse(address).map_err(|err| NotifierError::InvalidRecipient(err.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(NotifierError::InvalidRecipient(String::from(
+            "webhook url must use http or https",
+        )));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| NotifierError::InvalidRecipient(String::from("webhook url has no host")))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    resolve_public_addrs(host.trim_start_matches('[').trim_end_matches(']'), port).await?;
+    Ok(())
+}
+
+async fn resolve_public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>, NotifierError> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, port))
+        .await
+        .map_err(|err| NotifierError::InvalidRecipient(format!("cannot resolve {host}: {err}")))?
+        .collect();
+
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(NotifierError::InvalidRecipient(format!(
+            "{host} is not a public address"
+        )));
+    }
+    Ok(addrs)
+}
+
+/// Loopback, private, link-local, shared, multicast and reserved ranges are
+/// all off limits.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || first == 0
+                || first >= 240
+                || (first == 100 && second & 0xc0 == 64)
+                || (first == 198 && second & 0xfe == 18))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+struct PublicAddrResolver;
+
+impl Resolve for PublicAddrResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public_addrs(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Signs `"{timestamp}.{body}"` so receivers can reject both tampered and
+/// replayed payloads.
+pub fn sign_webhook_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac can only accept secret of a perticular length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(
+        &self,
+        recipient: &Recipient<'_>,
+        notification: &Notification,
+    ) -> Result<(), NotifierError> {
+        if !self.allow_private_targets {
+            ensure_public_webhook_url(recipient.address).await?;
+        }
+
+        let secret = recipient
+            .secret
+            .ok_or_else(|| NotifierError::InvalidRecipient(String::from("missing secret")))?;
+        let body = serde_json::to_string(notification)
+            .map_err(|err| NotifierError::InvalidRecipient(err.to_string()))?;
+        let timestamp = Utc::now().timestamp();
+        let signature = sign_webhook_payload(secret, timestamp, &body);
+
+        self.http_client
+            .post(recipient.address)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header("X-Fiya-Timestamp", timestamp.to_string())
+            .header("X-Fiya-Signature", format!("sha256={signature}"))
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::notifiers::stand_in::http_stand_in;
+
+    fn notification() -> Notification {
+        Notification {
+            event: String::from("alert.opened"),
+            subject: String::from("Critical temperature alert on cage C-1"),
+            body: String::from("Temperature crossed the threshold."),
+            sensitive: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_signed_payload() {
+        let (url, mut received) = http_stand_in(StatusCode::OK).await;
+        let recipient = Recipient {
+            address: &url,
+            secret: Some("webhook-secret"),
+        };
+
+        WebhookNotifier::allowing_private_targets()
+            .send(&recipient, &notification())
+            .await
+            .unwrap();
+
+        let request = received.recv().await.unwrap();
+        let timestamp: i64 = request.headers["x-fiya-timestamp"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected = format!(
+            "sha256={}",
+            sign_webhook_payload("webhook-secret", timestamp, &request.body)
+        );
+        assert_eq!(request.headers["x-fiya-signature"], expected.as_str());
+        assert_eq!(request.headers["content-type"], "application/json");
+
+        let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload["event"], "alert.opened");
+        assert!(payload.get("sensitive").is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_status() {
+        let (url, _received) = http_stand_in(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let recipient = Recipient {
+            address: &url,
+            secret: Some("webhook-secret"),
+        };
+
+        let sent = WebhookNotifier::allowing_private_targets()
+            .send(&recipient, &notification())
+            .await;
+        assert!(matches!(sent, Err(NotifierError::Http(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_loopback_targets() {
+        let (url, mut received) = http_stand_in(StatusCode::OK).await;
+        let localhost_url = url.replace("127.0.0.1", "localhost");
+
+        for address in [url.as_str(), localhost_url.as_str()] {
+            let recipient = Recipient {
+                address,
+                secret: Some("webhook-secret"),
+            };
+            let sent = WebhookNotifier::new()
+                .send(&recipient, &notification())
+                .await;
+            assert!(matches!(sent, Err(NotifierError::InvalidRecipient(_))));
+        }
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_names_with_private_addresses() {
+        let resolved = PublicAddrResolver
+            .resolve("localhost".parse().unwrap())
+            .await;
+        assert!(resolved.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_accepts_public_http_urls() {
+        for address in [
+            "http://127.0.0.1/hook",
+            "http://localhost:8080/hook",
+            "http://10.0.0.5/hook",
+            "http://172.16.0.1/hook",
+            "https://192.168.1.10/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:10.0.0.5]/hook",
+            "ftp://8.8.8.8/hook",
+            "not a url",
+        ] {
+            assert!(
+                ensure_public_webhook_url(address).await.is_err(),
+                "{address} should be rejected"
+            );
+        }
+
+        for address in ["https://8.8.8.8/hook", "http://[2606:4700:4700::1111]/hook"] {
+            assert!(
+                ensure_public_webhook_url(address).await.is_ok(),
+                "{address} should be accepted"
+            );
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{options::ReturnDocument, ClientSession, Collection, Database};
+
+use crate::{
+    models::alert::{Alert, AlertComment, AlertMetric, AlertSeverity, AlertState, SensorBreach},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub struct AlertRepository {
+    alerts: Collection<Alert>,
+    sensor_breaches: Collection<SensorBreach>,
+}
+
+impl AlertRepository {
+    pub fn new(db: &Database) -> Self {
+        let alerts = db.collection("alerts");
+        let sensor_breaches = db.collection("sensor_breaches");
+
+        Self {
+            alerts,
+            sensor_breaches,
+        }
+    }
+
+    /// Records another out-of-range reading for a metric, keeping the time the
+    /// breach first started untouched while it continues.
+    pub async fn record_sensor_breach(
+        &self,
+        cage_id: &str,
+        metric: AlertMetric,
+        started_at: DateTime<Utc>,
+    ) -> Result<SensorBreach, ApiErrorResponse> {
+        let filter = doc! { "cage_id": cage_id, "metric": metric.to_string() };
+        let update = doc! {
+            "$setOnInsert": { "started_at": BsonDateTime::from_chrono(started_at) },
+            "$inc": { "readings": 1 },
+        };
+        let breach = self
+            .sensor_breaches
+            .find_one_and_update(filter, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(breach.unwrap_or(SensorBreach {
+            cage_id: cage_id.to_string(),
+            metric,
+            started_at,
+            readings: 1,
+        }))
+    }
+
+    pub async fn clear_sensor_breach(
+        &self,
+        cage_id: &str,
+        metric: AlertMetric,
+    ) -> Result<(), ApiErrorResponse> {
+        let filter = doc! { "cage_id": cage_id, "metric": metric.to_string() };
+        self.sensor_breaches
+            .delete_one(filter)
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+
+    /// Opens `alert`, or returns `None` when the cage already has an active
+    /// alert for the metric. Only one can be active at a time, enforced by a
+    /// partial unique index, so concurrent readings can't open duplicates.
+    pub async fn create_alert(&self, alert: Alert) -> Result<Option<Alert>, ApiErrorResponse> {
+        match self.alerts.insert_one(&alert).await {
+            Ok(_) => Ok(Some(alert)),
+            Err(err) if err.to_string().contains("E11000 duplicate key error") => Ok(None),
+            Err(err) => Err(internal_error(err)),
+        }
+    }
+
+    pub async fn find_active_alert(
+        &self,
+        cage_id: &str,
+        metric: AlertMetric,
+    ) -> Result<Option<Alert>, ApiErrorResponse> {
+        let filter = doc! {
+            "cage_id": cage_id,
+            "metric": metric.to_string(),
+            "active": true,
+        };
+        let alert = self.alerts.find_one(filter).await.map_err(internal_error)?;
+
+        Ok(alert)
+    }
+
+    pub async fn find_alert_by_id(&self, id: &str) -> Result<Option<Alert>, ApiErrorResponse> {
+        let obj_id = ObjectId::parse_str(id)
+            .map_err(|_| ApiErrorResponse::new(400, String::from("Invalid alert id")))?;
+        let alert = self
+            .alerts
+            .find_one(doc! { "_id": obj_id })
+            .await
+            .map_err(internal_error)?;
+
+        Ok(alert)
+    }
+
+    pub async fn update_alert_value(
+        &self,
+        id: ObjectId,
+        last_value: f32,
+        severity: AlertSeverity,
+    ) -> Result<(), ApiErrorResponse> {
+        let mut set = doc! {
+            "last_value": last_value,
+            "updated_at": BsonDateTime::from_chrono(Utc::now()),
+        };
+        // Severity only ever escalates while the condition lasts.
+        if severity == AlertSeverity::Critical {
+            set.insert("severity", severity.to_string());
+        }
+        self.alerts
+            .update_one(doc! { "_id": id }, doc! { "$set": set })
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+
+    /// Marks the condition as gone and resolves the alert on the system's
+    /// behalf unless somebody already resolved it.
+    pub async fn clear_alert(
+        &self,
+        id: ObjectId,
+        last_value: f32,
+    ) -> Result<Option<Alert>, ApiErrorResponse> {
+        let now = BsonDateTime::from_chrono(Utc::now());
+        let update = vec![doc! { "$set": {
+            "active": false,
+            "last_value": last_value,
+            "cleared_at": now,
+            "state": AlertState::Resolved.to_string(),
+            "resolved_at": { "$ifNull": ["$resolved_at", now] },
+            "updated_at": now,
+        } }];
+        let alert = self
+            .alerts
+            .find_one_and_update(doc! { "_id": id }, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+        Ok(alert)
+    }
+
+    pub async fn acknowledge_alert(
+        &self,
+        id: ObjectId,
+        user_id: &str,
+    ) -> Result<Option<Alert>, ApiErrorResponse> {
+        let now = BsonDateTime::from_chrono(Utc::now());
+        let filter = doc! { "_id": id, "state": AlertState::Open.to_string() };
+        let update = doc! { "$set": {
+            "state": AlertState::Acknowledged.to_string(),
+            "acknowledged_by": user_id,
+            "acknowledged_at": now,
+            "updated_at": now,
+        } };
+        let alert = self
+            .alerts
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+        Ok(alert)
+    }
+
+    pub async fn resolve_alert(
+        &self,
+        id: ObjectId,
+        user_id: &str,
+    ) -> Result<Option<Alert>, ApiErrorResponse> {
+        let now = BsonDateTime::from_chrono(Utc::now());
+        let filter = doc! {
+            "_id": id,
+            "state": { "$ne": AlertState::Resolved.to_string() },
+        };
+        let update = doc! { "$set": {
+            "state": AlertState::Resolved.to_string(),
+            "resolved_by": user_id,
+            "resolved_at": now,
+            "updated_at": now,
+        } };
+        let alert = self
+            .alerts
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+        Ok(alert)
+    }
+
+    pub async fn add_alert_comment(
+        &self,
+        id: ObjectId,
+        comment: AlertComment,
+    ) -> Result<Option<Alert>, ApiErrorResponse> {
+        let comment = bson::to_document(&comment).map_err(internal_error)?;
+        let update = doc! {
+            "$push": { "comments": comment },
+            "$set": { "updated_at": BsonDateTime::from_chrono(Utc::now()) },
+        };
+        let alert = self
+            .alerts
+            .find_one_and_update(doc! { "_id": id }, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+        Ok(alert)
+    }
+
+    pub async fn find_alerts_with_pagination(
+        &self,
+        cage_ids: &[String],
+        state: Option<AlertState>,
+        severity: Option<AlertSeverity>,
+        offset: u64,
+        limit: u64,
+    ) -> Result<(Vec<Alert>, u64), ApiErrorResponse> {
+        let mut filter: Document = doc! { "cage_id": { "$in": cage_ids } };
+        if let Some(state) = state {
+            filter.insert("state", state.to_string());
+        }
+        if let Some(severity) = severity {
+            filter.insert("severity", severity.to_string());
+        }
+        let sort = doc! { "opened_at": -1 };
+
+        let total_alerts = self
+            .alerts
+            .count_documents(filter.clone())
+            .await
+            .map_err(internal_error)?;
+
+        let cursor = self
+            .alerts
+            .find(filter)
+            .sort(sort)
+            .skip(offset)
+            .limit(limit as i64)
+            .await
+            .map_err(internal_error)?;
+        let alerts: Vec<Alert> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok((alerts, total_alerts))
+    }
+
+    pub async fn delete_cage_alerts(
+        &self,
+        session: &mut ClientSession,
+        cage_id: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        let filter = doc! { "cage_id": cage_id };
+        self.alerts
+            .delete_many(filter.clone())
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.sensor_breaches
+            .delete_many(filter)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+}
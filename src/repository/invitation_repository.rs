@@ -0,0 +1,160 @@
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{options::ReturnDocument, ClientSession, Collection, Database};
+
+use crate::{
+    models::invitation::Invitation,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub struct InvitationRepository {
+    invitations: Collection<Invitation>,
+}
+
+impl InvitationRepository {
+    pub fn new(db: &Database) -> Self {
+        let invitations = db.collection("invitations");
+
+        Self { invitations }
+    }
+
+    pub async fn create_invitation(
+        &self,
+        session: &mut ClientSession,
+        invitation: Invitation,
+    ) -> Result<Invitation, ApiErrorResponse> {
+        self.invitations
+            .insert_one(&invitation)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        Ok(invitation)
+    }
+
+    /// Invitations sent by `invited_by` that are neither accepted nor revoked,
+    /// including expired ones so they can be resent.
+    pub async fn find_open_invitations_by_inviter(
+        &self,
+        invited_by: &str,
+    ) -> Result<Vec<Invitation>, ApiErrorResponse> {
+        let filter = doc! {
+            "invited_by": invited_by,
+            "accepted_at": { "$exists": false },
+            "revoked_at": { "$exists": false },
+        };
+        let cursor = self
+            .invitations
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(internal_error)?;
+        let invitations: Vec<Invitation> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok(invitations)
+    }
+
+    pub async fn find_invitation_by_id(
+        &self,
+        id: ObjectId,
+    ) -> Result<Option<Invitation>, ApiErrorResponse> {
+        self.invitations
+            .find_one(doc! { "_id": id })
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Swaps in a fresh token and expiry for an invitation that is still open.
+    pub async fn reissue_invitation(
+        &self,
+        id: ObjectId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<Invitation>, ApiErrorResponse> {
+        let filter = doc! {
+            "_id": id,
+            "accepted_at": { "$exists": false },
+            "revoked_at": { "$exists": false },
+        };
+        let update = doc! { "$set": {
+            "token_hash": token_hash,
+            "expires_at": BsonDateTime::from_chrono(expires_at),
+            "updated_at": BsonDateTime::now(),
+        } };
+        self.invitations
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)
+    }
+
+    pub async fn revoke_invitation(&self, id: ObjectId) -> Result<bool, ApiErrorResponse> {
+        let now = BsonDateTime::now();
+        let filter = doc! {
+            "_id": id,
+            "accepted_at": { "$exists": false },
+            "revoked_at": { "$exists": false },
+        };
+        let update = doc! { "$set": { "revoked_at": now, "updated_at": now } };
+        let result = self
+            .invitations
+            .update_one(filter, update)
+            .await
+            .map_err(internal_error)?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Marks a pending invitation as accepted. Only one caller can ever
+    /// succeed for a given token.
+    pub async fn accept_invitation(
+        &self,
+        session: &mut ClientSession,
+        token_hash: &str,
+    ) -> Result<Option<Invitation>, ApiErrorResponse> {
+        let now = BsonDateTime::now();
+        let filter = doc! {
+            "token_hash": token_hash,
+            "accepted_at": { "$exists": false },
+            "revoked_at": { "$exists": false },
+            "expires_at": { "$gt": now },
+        };
+        let update = doc! { "$set": { "accepted_at": now, "updated_at": now } };
+        self.invitations
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Keeps open invitations pointed at the user's current address.
+    pub async fn update_open_invitation_email(
+        &self,
+        user_id: &str,
+        email: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        let filter = doc! {
+            "user_id": user_id,
+            "accepted_at": { "$exists": false },
+            "revoked_at": { "$exists": false },
+        };
+        let update = doc! { "$set": { "email": email, "updated_at": BsonDateTime::now() } };
+        self.invitations
+            .update_many(filter, update)
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+
+    pub async fn delete_user_invitations(
+        &self,
+        session: &mut ClientSession,
+        user_id: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        self.invitations
+            .delete_many(doc! { "user_id": user_id })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+}
@@ -1,2 +1,7 @@
+pub mod alert_repository;
+pub mod invitation_repository;
+pub mod notification_repository;
+pub mod plan_repository;
+pub mod provisioning_repository;
 pub mod spm_repository;
 pub mod user_repository;
@@ -0,0 +1,283 @@
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{options::ReturnDocument, Collection, Database};
+
+use crate::{
+    models::notification::{
+        DeliveryStatus, NotificationChannel, NotificationDelivery, REDACTED_BODY,
+    },
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub struct NotificationRepository {
+    channels: Collection<NotificationChannel>,
+    deliveries: Collection<NotificationDelivery>,
+}
+
+impl NotificationRepository {
+    pub fn new(db: &Database) -> Self {
+        let channels = db.collection("notification_channels");
+        let deliveries = db.collection("notification_deliveries");
+
+        Self {
+            channels,
+            deliveries,
+        }
+    }
+
+    pub async fn create_channel(
+        &self,
+        channel: NotificationChannel,
+    ) -> Result<NotificationChannel, ApiErrorResponse> {
+        self.channels
+            .insert_one(&channel)
+            .await
+            .map_err(internal_error)?;
+        Ok(channel)
+    }
+
+    pub async fn find_channels_by_user_id(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<NotificationChannel>, ApiErrorResponse> {
+        let cursor = self
+            .channels
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(internal_error)?;
+        let channels: Vec<NotificationChannel> =
+            cursor.try_collect().await.map_err(internal_error)?;
+        Ok(channels)
+    }
+
+    pub async fn find_enabled_channels_by_user_id(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<NotificationChannel>, ApiErrorResponse> {
+        let cursor = self
+            .channels
+            .find(doc! { "user_id": user_id, "enabled": true })
+            .await
+            .map_err(internal_error)?;
+        let channels: Vec<NotificationChannel> =
+            cursor.try_collect().await.map_err(internal_error)?;
+        Ok(channels)
+    }
+
+    pub async fn delete_channel(
+        &self,
+        user_id: &str,
+        channel_id: ObjectId,
+    ) -> Result<bool, ApiErrorResponse> {
+        let result = self
+            .channels
+            .delete_one(doc! { "_id": channel_id, "user_id": user_id })
+            .await
+            .map_err(internal_error)?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn enqueue_deliveries(
+        &self,
+        deliveries: Vec<NotificationDelivery>,
+    ) -> Result<(), ApiErrorResponse> {
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+        self.deliveries
+            .insert_many(deliveries)
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+
+    /// Claims the next due delivery by pushing its next attempt out by the
+    /// lease, so a worker that dies mid-send only delays the retry.
+    pub async fn claim_due_delivery(
+        &self,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<NotificationDelivery>, ApiErrorResponse> {
+        let now = BsonDateTime::from_chrono(Utc::now());
+        let filter = doc! {
+            "status": DeliveryStatus::Pending.to_string(),
+            "next_attempt_at": { "$lte": now },
+        };
+        let update = doc! {
+            "$set": {
+                "next_attempt_at": BsonDateTime::from_chrono(lease_until),
+                "updated_at": now,
+            },
+            "$inc": { "attempts": 1 },
+        };
+        let delivery = self
+            .deliveries
+            .find_one_and_update(filter, update)
+            .sort(doc! { "next_attempt_at": 1 })
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?;
+        Ok(delivery)
+    }
+
+    pub async fn mark_delivered(
+        &self,
+        id: ObjectId,
+        redact_body: bool,
+    ) -> Result<(), ApiErrorResponse> {
+        let mut set = doc! {
+            "status": DeliveryStatus::Delivered.to_string(),
+            "updated_at": BsonDateTime::from_chrono(Utc::now()),
+        };
+        if redact_body {
+            set.insert("body", REDACTED_BODY);
+        }
+        self.deliveries
+            .update_one(doc! { "_id": id }, doc! { "$set": set })
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+
+    /// Schedules another attempt, or gives up when `next_attempt_at` is `None`.
+    pub async fn mark_attempt_failed(
+        &self,
+        id: ObjectId,
+        error: String,
+        next_attempt_at: Option<DateTime<Utc>>,
+        redact_body: bool,
+    ) -> Result<(), ApiErrorResponse> {
+        let mut set = doc! {
+            "last_error": error,
+            "updated_at": BsonDateTime::from_chrono(Utc::now()),
+        };
+        if redact_body {
+            set.insert("body", REDACTED_BODY);
+        }
+        match next_attempt_at {
+            Some(next_attempt_at) => {
+                set.insert(
+                    "next_attempt_at",
+                    BsonDateTime::from_chrono(next_attempt_at),
+                );
+            }
+            None => {
+                set.insert("status", DeliveryStatus::Failed.to_string());
+            }
+        }
+        self.deliveries
+            .update_one(doc! { "_id": id }, doc! { "$set": set })
+            .await
+            .map_err(internal_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{config::database::test_mongodb_connection, models::notification::ChannelKind};
+
+    fn due_delivery(sensitive: bool) -> NotificationDelivery {
+        NotificationDelivery {
+            id: ObjectId::new(),
+            user_id: None,
+            kind: ChannelKind::Email,
+            address: String::from("farmer@example.com"),
+            secret: None,
+            event: String::from("user.invited"),
+            subject: String::from("You've been invited to Fiya"),
+            body: String::from("Use this invite code: secret-code"),
+            sensitive,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Utc::now() - Duration::seconds(1),
+            last_error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB server at TEST_DATABASE_URL"]
+    async fn claimed_delivery_is_leased_until_the_lease_runs_out() {
+        let client = test_mongodb_connection().await;
+        let db = client.database(&format!("fiya_test_{}", ObjectId::new()));
+        let notification_repo = NotificationRepository::new(&db);
+        let delivery = due_delivery(false);
+        notification_repo
+            .enqueue_deliveries(vec![delivery.clone()])
+            .await
+            .unwrap();
+
+        let lease_until = Utc::now() + Duration::milliseconds(500);
+        let claimed = notification_repo
+            .claim_due_delivery(lease_until)
+            .await
+            .unwrap()
+            .expect("due delivery should be claimed");
+        assert_eq!(claimed.id, delivery.id);
+        assert_eq!(claimed.attempts, 1);
+        assert_eq!(
+            claimed.next_attempt_at.timestamp_millis(),
+            lease_until.timestamp_millis()
+        );
+
+        // Another worker can't claim it while the lease holds...
+        let contended = notification_repo
+            .claim_due_delivery(Utc::now() + Duration::minutes(2))
+            .await
+            .unwrap();
+        assert!(contended.is_none());
+
+        // ...but picks it up again once the lease has run out.
+        tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+        let reclaimed = notification_repo
+            .claim_due_delivery(Utc::now() + Duration::minutes(2))
+            .await
+            .unwrap()
+            .expect("expired lease should be reclaimable");
+        assert_eq!(reclaimed.id, delivery.id);
+        assert_eq!(reclaimed.attempts, 2);
+
+        db.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB server at TEST_DATABASE_URL"]
+    async fn settled_sensitive_deliveries_are_redacted() {
+        let client = test_mongodb_connection().await;
+        let db = client.database(&format!("fiya_test_{}", ObjectId::new()));
+        let notification_repo = NotificationRepository::new(&db);
+        let delivered = due_delivery(true);
+        let failed = due_delivery(true);
+        notification_repo
+            .enqueue_deliveries(vec![delivered.clone(), failed.clone()])
+            .await
+            .unwrap();
+
+        notification_repo
+            .mark_delivered(delivered.id, true)
+            .await
+            .unwrap();
+        notification_repo
+            .mark_attempt_failed(failed.id, String::from("timed out"), None, true)
+            .await
+            .unwrap();
+
+        for id in [delivered.id, failed.id] {
+            let delivery = notification_repo
+                .deliveries
+                .find_one(doc! { "_id": id })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(delivery.body, REDACTED_BODY);
+        }
+
+        db.drop().await.unwrap();
+    }
+}
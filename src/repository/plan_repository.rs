@@ -0,0 +1,58 @@
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use mongodb::{options::ReturnDocument, Collection, Database};
+
+use crate::{
+    models::plan::Plan,
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub struct PlanRepository {
+    plans: Collection<Plan>,
+}
+
+impl PlanRepository {
+    pub fn new(db: &Database) -> Self {
+        let plans = db.collection("plans");
+
+        Self { plans }
+    }
+
+    pub async fn find_plan_by_admin_id(
+        &self,
+        admin_id: ObjectId,
+    ) -> Result<Option<Plan>, ApiErrorResponse> {
+        self.plans
+            .find_one(doc! { "admin_id": admin_id })
+            .await
+            .map_err(internal_error)
+    }
+
+    pub async fn upsert_plan(&self, plan: &Plan) -> Result<Plan, ApiErrorResponse> {
+        let export_formats: Vec<String> = plan
+            .export_formats
+            .iter()
+            .map(|format| format.to_string())
+            .collect();
+        let update = doc! {
+            "$set": {
+                "name": &plan.name,
+                "max_customers": plan.max_customers as i64,
+                "max_cages": plan.max_cages as i64,
+                "retention_days": plan.retention_days as i64,
+                "export_formats": export_formats,
+                "updated_at": BsonDateTime::from_chrono(plan.updated_at),
+            },
+            "$setOnInsert": {
+                "_id": plan.id,
+                "created_at": BsonDateTime::from_chrono(plan.created_at),
+            },
+        };
+        self.plans
+            .find_one_and_update(doc! { "admin_id": plan.admin_id }, update)
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| ApiErrorResponse::new(500, String::from("Plan was not saved")))
+    }
+}
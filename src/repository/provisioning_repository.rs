@@ -0,0 +1,188 @@
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use mongodb::{options::ReturnDocument, ClientSession, Collection, Database};
+
+use crate::{
+    models::provisioning::{ClaimStatus, DeviceClaim, ProvisioningSheet},
+    utils::{error_handler::internal_error, response::ApiErrorResponse},
+};
+
+pub struct ProvisioningRepository {
+    device_claims: Collection<DeviceClaim>,
+    provisioning_sheets: Collection<ProvisioningSheet>,
+}
+
+impl ProvisioningRepository {
+    pub fn new(db: &Database) -> Self {
+        let device_claims = db.collection("device_claims");
+        let provisioning_sheets = db.collection("provisioning_sheets");
+
+        Self {
+            device_claims,
+            provisioning_sheets,
+        }
+    }
+
+    pub async fn create_device_claims(
+        &self,
+        device_claims: &[DeviceClaim],
+    ) -> Result<(), ApiErrorResponse> {
+        match self.device_claims.insert_many(device_claims).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.to_string().contains("E11000 duplicate key error") => Err(
+                ApiErrorResponse::new(400, String::from("Device is already registered")),
+            ),
+            Err(err) => Err(internal_error(err)),
+        }
+    }
+
+    pub async fn find_device_claim_by_code_hash(
+        &self,
+        claim_code_hash: &str,
+    ) -> Result<Option<DeviceClaim>, ApiErrorResponse> {
+        self.device_claims
+            .find_one(doc! { "claim_code_hash": claim_code_hash })
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Moves an unclaimed device to claimed. Returns `false` if somebody else
+    /// claimed it first.
+    pub async fn mark_device_claimed(
+        &self,
+        session: &mut ClientSession,
+        claim_id: ObjectId,
+        cage_id: &str,
+        user_id: &str,
+    ) -> Result<bool, ApiErrorResponse> {
+        let now = BsonDateTime::now();
+        let filter = doc! { "_id": claim_id, "status": ClaimStatus::Unclaimed.to_string() };
+        let update = doc! { "$set": {
+            "status": ClaimStatus::Claimed.to_string(),
+            "cage_id": cage_id,
+            "claimed_by": user_id,
+            "claimed_at": now,
+            "updated_at": now,
+        } };
+        let result = self
+            .device_claims
+            .update_one(filter, update)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(result.modified_count > 0)
+    }
+
+    /// Moves a claimed device to delivered, which only ever succeeds once.
+    pub async fn mark_device_delivered(
+        &self,
+        claim_id: ObjectId,
+    ) -> Result<Option<DeviceClaim>, ApiErrorResponse> {
+        let now = BsonDateTime::now();
+        let filter = doc! { "_id": claim_id, "status": ClaimStatus::Claimed.to_string() };
+        let update = doc! { "$set": {
+            "status": ClaimStatus::Delivered.to_string(),
+            "delivered_at": now,
+            "updated_at": now,
+        } };
+
+        self.device_claims
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Returns the claims on a deleted cage to unclaimed, so the device can be
+    /// claimed again with the code printed on it.
+    pub async fn release_cage_device_claims(
+        &self,
+        session: &mut ClientSession,
+        cage_id: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        let update = doc! {
+            "$set": {
+                "status": ClaimStatus::Unclaimed.to_string(),
+                "updated_at": BsonDateTime::now(),
+            },
+            "$unset": {
+                "cage_id": "",
+                "claimed_by": "",
+                "claimed_at": "",
+                "delivered_at": "",
+            },
+        };
+        self.device_claims
+            .update_many(doc! { "cage_id": cage_id }, update)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+
+    pub async fn create_provisioning_sheet(
+        &self,
+        session: &mut ClientSession,
+        provisioning_sheet: &ProvisioningSheet,
+    ) -> Result<(), ApiErrorResponse> {
+        self.provisioning_sheets
+            .insert_one(provisioning_sheet)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+
+    pub async fn find_provisioning_sheet(
+        &self,
+        sheet_id: ObjectId,
+        user_id: &str,
+    ) -> Result<Option<ProvisioningSheet>, ApiErrorResponse> {
+        self.provisioning_sheets
+            .find_one(doc! { "_id": sheet_id, "created_by": user_id })
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Removes a user's provisioning sheet once it has been downloaded.
+    /// Returns false if another download already consumed it.
+    pub async fn consume_provisioning_sheet(
+        &self,
+        sheet_id: ObjectId,
+        user_id: &str,
+    ) -> Result<bool, ApiErrorResponse> {
+        let result = self
+            .provisioning_sheets
+            .delete_one(doc! { "_id": sheet_id, "created_by": user_id })
+            .await
+            .map_err(internal_error)?;
+
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Drops a deleted cage from pending provisioning sheets, and the sheets
+    /// left without any cage.
+    pub async fn remove_cage_from_provisioning_sheets(
+        &self,
+        session: &mut ClientSession,
+        cage_id: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        self.provisioning_sheets
+            .update_many(
+                doc! { "cage_ids": cage_id },
+                doc! { "$pull": { "cage_ids": cage_id } },
+            )
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.provisioning_sheets
+            .delete_many(doc! { "cage_ids": { "$size": 0 } })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+}
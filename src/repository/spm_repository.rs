@@ -1,28 +1,40 @@
-use bson::{doc, DateTime as BsonDateTime};
+use bson::{doc, DateTime as BsonDateTime, Document};
 use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
-use mongodb::{ClientSession, Collection, Database};
+use mongodb::{error::ErrorKind, options::ReturnDocument, ClientSession, Collection, Database};
 
 use crate::{
-    models::spm::{Cage, HealthSettings, SpmDeviceToken},
+    models::spm::{
+        BucketSize, Cage, CageReading, DeviceNonce, DiseaseRiskBucket, HealthSettings,
+        ReadingBucket, SensorMetric, SpmDevice, SpmDeviceToken,
+    },
     utils::{error_handler::internal_error, response::ApiErrorResponse},
 };
 
 pub struct SpmRepository {
     cages: Collection<Cage>,
+    cage_readings: Collection<CageReading>,
     device_tokens: Collection<SpmDeviceToken>,
+    devices: Collection<SpmDevice>,
+    device_nonces: Collection<DeviceNonce>,
     health_settings: Collection<HealthSettings>,
 }
 
 impl SpmRepository {
     pub fn new(db: &Database) -> Self {
-        let cages = db.collection("cage");
+        let cages = db.collection("cages");
+        let cage_readings = db.collection("cage_readings");
         let device_tokens = db.collection("device_token");
+        let devices = db.collection("devices");
+        let device_nonces = db.collection("device_nonces");
         let health_settings = db.collection("health_settings");
 
         Self {
             cages,
+            cage_readings,
             device_tokens,
+            devices,
+            device_nonces,
             health_settings,
         }
     }
@@ -65,6 +77,53 @@ impl SpmRepository {
         Ok(cage)
     }
 
+    /// Applies `changes` to a cage and returns it as updated.
+    pub async fn update_cage(
+        &self,
+        cage_id: &str,
+        mut changes: Document,
+    ) -> Result<Option<Cage>, ApiErrorResponse> {
+        changes.insert("updated_at", BsonDateTime::now());
+        self.cages
+            .find_one_and_update(doc! { "cage_id": cage_id }, doc! { "$set": changes })
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Deletes a cage together with its health settings, device token and
+    /// device. Readings live in a time-series collection, which can't be
+    /// written in a transaction, so they go through `delete_cage_readings`.
+    pub async fn delete_cage(
+        &self,
+        session: &mut ClientSession,
+        cage_id: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        let filter = doc! { "cage_id": cage_id };
+        self.health_settings
+            .delete_many(filter.clone())
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.device_tokens
+            .delete_one(doc! { "_id": cage_id })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.devices
+            .delete_one(doc! { "_id": cage_id })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.cages
+            .delete_one(filter)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+
     pub async fn find_device_token_by_id(
         &self,
         id: &str,
@@ -79,11 +138,34 @@ impl SpmRepository {
         Ok(device_token)
     }
 
-    pub async fn find_all_users_cage_data(
+    /// Replaces a device token record only if nobody else has rotated it
+    /// since it was read. Returns whether the replacement happened.
+    pub async fn replace_device_token(
         &self,
-        assigned_monitor: String,
+        spm_device_token: &SpmDeviceToken,
+        expected_version: u32,
+    ) -> Result<bool, ApiErrorResponse> {
+        let filter = doc! { "_id": &spm_device_token.id, "version": expected_version };
+        let result = self
+            .device_tokens
+            .replace_one(filter, spm_device_token)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(result.matched_count > 0)
+    }
+
+    /// Cages assigned to any of `assigned_monitors`, along with the cages
+    /// listed in `cage_ids`.
+    pub async fn find_cages_by_assigned_monitors(
+        &self,
+        assigned_monitors: &[String],
+        cage_ids: &[String],
     ) -> Result<Vec<Cage>, ApiErrorResponse> {
-        let filter = doc! { "assigned_monitor": assigned_monitor };
+        let filter = doc! { "$or": [
+            { "assigned_monitor": { "$in": assigned_monitors } },
+            { "cage_id": { "$in": cage_ids } },
+        ] };
         let sort = doc! { "created_at": -1 };
 
         let cursor = self
@@ -97,61 +179,302 @@ impl SpmRepository {
         Ok(cages)
     }
 
-    pub async fn find_all_users_cage_data_with_pagination(
+    pub async fn count_cages_by_assigned_monitors(
+        &self,
+        assigned_monitors: &[String],
+    ) -> Result<u64, ApiErrorResponse> {
+        self.cages
+            .count_documents(doc! { "assigned_monitor": { "$in": assigned_monitors } })
+            .await
+            .map_err(internal_error)
+    }
+
+    pub async fn find_cage_ids_by_assigned_monitors(
+        &self,
+        assigned_monitors: &[String],
+    ) -> Result<Vec<String>, ApiErrorResponse> {
+        let cages: Vec<Cage> = self
+            .cages
+            .find(doc! { "assigned_monitor": { "$in": assigned_monitors } })
+            .await
+            .map_err(internal_error)?
+            .try_collect()
+            .await
+            .map_err(internal_error)?;
+
+        Ok(cages.into_iter().map(|cage| cage.cage_id).collect())
+    }
+
+    pub async fn find_cages_by_cage_ids(
+        &self,
+        cage_ids: &[String],
+    ) -> Result<Vec<Cage>, ApiErrorResponse> {
+        let filter = doc! { "cage_id": { "$in": cage_ids } };
+
+        let cursor = self.cages.find(filter).await.map_err(internal_error)?;
+        let cages: Vec<Cage> = cursor.try_collect().await.map_err(internal_error)?;
+
+        Ok(cages)
+    }
+
+    pub async fn find_cage_readings(
         &self,
-        assigned_monitor: String,
+        cage_ids: &[String],
+    ) -> Result<Vec<CageReading>, ApiErrorResponse> {
+        let filter = doc! { "cage_id": { "$in": cage_ids } };
+        let sort = doc! { "timestamp": -1 };
+
+        let cursor = self
+            .cage_readings
+            .find(filter)
+            .sort(sort)
+            .await
+            .map_err(internal_error)?;
+        let readings: Vec<CageReading> = cursor.try_collect().await.map_err(internal_error)?;
+
+        Ok(readings)
+    }
+
+    /// Returns the newest reading of each cage that has reported at least once.
+    pub async fn find_latest_cage_readings(
+        &self,
+        cage_ids: &[String],
+    ) -> Result<Vec<CageReading>, ApiErrorResponse> {
+        let pipeline = vec![
+            doc! { "$match": { "cage_id": { "$in": cage_ids } } },
+            doc! { "$sort": { "cage_id": 1, "timestamp": -1 } },
+            doc! { "$group": { "_id": "$cage_id", "reading": { "$first": "$$ROOT" } } },
+            doc! { "$replaceRoot": { "newRoot": "$reading" } },
+        ];
+
+        let cursor = self
+            .cage_readings
+            .aggregate(pipeline)
+            .with_type::<CageReading>()
+            .await
+            .map_err(internal_error)?;
+        let readings: Vec<CageReading> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok(readings)
+    }
+
+    pub async fn find_cage_readings_with_pagination(
+        &self,
+        cage_ids: &[String],
         offset: u64,
         limit: u64,
-    ) -> Result<(Vec<Cage>, u64), ApiErrorResponse> {
-        let filter = doc! { "assigned_monitor": assigned_monitor };
-        let sort = doc! { "created_at": -1 };
+    ) -> Result<(Vec<CageReading>, u64), ApiErrorResponse> {
+        let filter = doc! { "cage_id": { "$in": cage_ids } };
+        let sort = doc! { "timestamp": -1 };
 
         let total_cage_data = self
-            .cages
+            .cage_readings
             .count_documents(filter.clone())
             .await
             .map_err(internal_error)?;
 
         let cursor = self
-            .cages
+            .cage_readings
             .find(filter)
             .sort(sort)
             .skip(offset)
             .limit(limit as i64)
             .await
             .map_err(internal_error)?;
-        let cages: Vec<Cage> = cursor.try_collect().await.map_err(internal_error)?;
-        Ok((cages, total_cage_data))
+        let readings: Vec<CageReading> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok((readings, total_cage_data))
     }
 
-    pub async fn find_cage_data_by_date_range(
+    pub async fn find_cage_readings_by_date_range(
         &self,
         cage_id: &str,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-    ) -> Result<Vec<Cage>, ApiErrorResponse> {
+    ) -> Result<Vec<CageReading>, ApiErrorResponse> {
         let filter = doc! {
             "cage_id": cage_id,
-            "created_at": {
+            "timestamp": {
                 "$gte": BsonDateTime::from_chrono(start_date),
                 "$lte": BsonDateTime::from_chrono(end_date),
             }
         };
+        let sort = doc! { "timestamp": 1 };
 
-        let cage_cursor = self.cages.find(filter).await.map_err(internal_error)?;
-        let cages: Vec<Cage> = cage_cursor.try_collect().await.map_err(internal_error)?;
-        Ok(cages)
+        let cursor = self
+            .cage_readings
+            .find(filter)
+            .sort(sort)
+            .await
+            .map_err(internal_error)?;
+        let readings: Vec<CageReading> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok(readings)
+    }
+
+    /// Downsamples a cage's readings into fixed-size time buckets with the
+    /// min, max and average of each requested metric, oldest first.
+    pub async fn aggregate_cage_readings(
+        &self,
+        cage_id: &str,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket_size: BucketSize,
+        metrics: &[SensorMetric],
+    ) -> Result<Vec<ReadingBucket>, ApiErrorResponse> {
+        let mut group = doc! {
+            "_id": { "$dateTrunc": {
+                "date": "$timestamp",
+                "unit": bucket_size.unit,
+                "binSize": bucket_size.bin_size as i64,
+            } },
+            "count": { "$sum": 1 },
+        };
+        let mut project = doc! { "_id": 0, "bucket_start": "$_id", "count": 1 };
+        for metric in metrics {
+            let field = format!("${metric}");
+            group.insert(format!("{metric}_min"), doc! { "$min": &field });
+            group.insert(format!("{metric}_max"), doc! { "$max": &field });
+            group.insert(format!("{metric}_avg"), doc! { "$avg": &field });
+            project.insert(
+                metric.to_string(),
+                doc! {
+                    "min": format!("${metric}_min"),
+                    "max": format!("${metric}_max"),
+                    "avg": format!("${metric}_avg"),
+                },
+            );
+        }
+
+        let pipeline: Vec<Document> = vec![
+            doc! { "$match": {
+                "cage_id": cage_id,
+                "timestamp": {
+                    "$gte": BsonDateTime::from_chrono(start_date),
+                    "$lte": BsonDateTime::from_chrono(end_date),
+                },
+            } },
+            doc! { "$group": group },
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$project": project },
+        ];
+
+        let cursor = self
+            .cage_readings
+            .aggregate(pipeline)
+            .with_type::<ReadingBucket>()
+            .await
+            .map_err(internal_error)?;
+        let buckets: Vec<ReadingBucket> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok(buckets)
+    }
+
+    /// Averages the recognition probabilities of a cage's readings into
+    /// fixed-size time buckets, oldest first.
+    pub async fn aggregate_disease_risk(
+        &self,
+        cage_id: &str,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        bucket_minutes: u32,
+    ) -> Result<Vec<DiseaseRiskBucket>, ApiErrorResponse> {
+        let pipeline = vec![
+            doc! { "$match": {
+                "cage_id": cage_id,
+                "timestamp": {
+                    "$gte": BsonDateTime::from_chrono(start_date),
+                    "$lte": BsonDateTime::from_chrono(end_date),
+                },
+            } },
+            doc! { "$group": {
+                "_id": { "$dateTrunc": {
+                    "date": "$timestamp",
+                    "unit": "minute",
+                    "binSize": bucket_minutes as i64,
+                } },
+                "readings": { "$sum": 1 },
+                "coccidiosis": { "$avg": "$object_recognition.coccidiosis" },
+                "newcastle": { "$avg": "$object_recognition.newcastle" },
+                "salmonella": { "$avg": "$object_recognition.salmonella" },
+                "healthy": { "$avg": "$object_recognition.healthy" },
+            } },
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$set": { "bucket_start": "$_id" } },
+        ];
+
+        let cursor = self
+            .cage_readings
+            .aggregate(pipeline)
+            .with_type::<DiseaseRiskBucket>()
+            .await
+            .map_err(internal_error)?;
+        let buckets: Vec<DiseaseRiskBucket> = cursor.try_collect().await.map_err(internal_error)?;
+        Ok(buckets)
+    }
+
+    pub async fn delete_cage_readings(&self, cage_id: &str) -> Result<u64, ApiErrorResponse> {
+        let result = self
+            .cage_readings
+            .delete_many(doc! { "cage_id": cage_id })
+            .await
+            .map_err(internal_error)?;
+
+        Ok(result.deleted_count)
+    }
+
+    pub async fn delete_cage_readings_before(
+        &self,
+        cage_ids: &[String],
+        before: DateTime<Utc>,
+    ) -> Result<u64, ApiErrorResponse> {
+        let filter = doc! {
+            "cage_id": { "$in": cage_ids },
+            "timestamp": { "$lt": BsonDateTime::from_chrono(before) },
+        };
+        let result = self
+            .cage_readings
+            .delete_many(filter)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(result.deleted_count)
+    }
+
+    pub async fn add_cage_reading(
+        &self,
+        reading: CageReading,
+    ) -> Result<CageReading, ApiErrorResponse> {
+        self.cage_readings
+            .insert_one(&reading)
+            .await
+            .map_err(internal_error)?;
+        Ok(reading)
     }
 
-    pub async fn add_cage_new_info(&self, new_cage_info: Cage) -> Result<Cage, ApiErrorResponse> {
-        let result = self.cages.insert_one(&new_cage_info).await;
+    /// Inserts readings without stopping at the first failure and returns the
+    /// indexes the server rejected; every other reading was stored.
+    pub async fn add_many_cage_readings(
+        &self,
+        readings: &[CageReading],
+    ) -> Result<Vec<(usize, String)>, ApiErrorResponse> {
+        let result = self
+            .cage_readings
+            .insert_many(readings)
+            .ordered(false)
+            .await;
 
         match result {
-            Ok(_) => Ok(new_cage_info),
-            Err(err) if err.to_string().contains("E11000 duplicate key error") => Err(
-                ApiErrorResponse::new(400, "Cage already exists".to_string()),
-            ),
-            Err(err) => Err(internal_error(err)),
+            Ok(_) => Ok(vec![]),
+            Err(err) => match *err.kind {
+                ErrorKind::InsertMany(ref insert_many_error)
+                    if insert_many_error.write_concern_error.is_none() =>
+                {
+                    Ok(insert_many_error
+                        .write_errors
+                        .iter()
+                        .flatten()
+                        .map(|write_error| (write_error.index, write_error.message.clone()))
+                        .collect())
+                }
+                _ => Err(internal_error(err)),
+            },
         }
     }
 
@@ -183,4 +506,83 @@ impl SpmRepository {
             .map_err(internal_error)?;
         Ok(health_settings)
     }
+
+    /// Marks a device as online and returns its record as it was before, so
+    /// callers can tell when a device comes back from being offline.
+    pub async fn record_device_seen(
+        &self,
+        cage_id: &str,
+        firmware_version: Option<&str>,
+        uptime_secs: Option<u64>,
+    ) -> Result<Option<SpmDevice>, ApiErrorResponse> {
+        let now = BsonDateTime::now();
+        let mut set = doc! { "online": true, "last_seen_at": now, "updated_at": now };
+        if let Some(firmware_version) = firmware_version {
+            set.insert("firmware_version", firmware_version);
+            set.insert(
+                "uptime_secs",
+                uptime_secs.map(|uptime_secs| uptime_secs as i64),
+            );
+        }
+        let update = doc! { "$set": set, "$unset": { "offline_since": "" } };
+
+        self.devices
+            .find_one_and_update(doc! { "_id": cage_id }, update)
+            .upsert(true)
+            .return_document(ReturnDocument::Before)
+            .await
+            .map_err(internal_error)
+    }
+
+    pub async fn find_device_by_cage_id(
+        &self,
+        cage_id: &str,
+    ) -> Result<Option<SpmDevice>, ApiErrorResponse> {
+        self.devices
+            .find_one(doc! { "_id": cage_id })
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Flips one online device that has been silent since `silent_since` to
+    /// offline. Returns `None` once no such device is left.
+    pub async fn mark_next_silent_device_offline(
+        &self,
+        silent_since: DateTime<Utc>,
+    ) -> Result<Option<SpmDevice>, ApiErrorResponse> {
+        let filter = doc! {
+            "online": true,
+            "last_seen_at": { "$lt": BsonDateTime::from_chrono(silent_since) },
+        };
+        let now = BsonDateTime::now();
+        let update = doc! { "$set": {
+            "online": false,
+            "offline_since": now,
+            "updated_at": now,
+        } };
+
+        self.devices
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(internal_error)
+    }
+
+    /// Remembers a signed request nonce. Returns `false` if the device already
+    /// used it, meaning the request is a replay.
+    pub async fn record_device_nonce(
+        &self,
+        cage_id: &str,
+        nonce: &str,
+    ) -> Result<bool, ApiErrorResponse> {
+        let device_nonce = DeviceNonce {
+            id: format!("{cage_id}:{nonce}"),
+            created_at: Utc::now(),
+        };
+        match self.device_nonces.insert_one(&device_nonce).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.to_string().contains("E11000 duplicate key error") => Ok(false),
+            Err(err) => Err(internal_error(err)),
+        }
+    }
 }
@@ -1,7 +1,9 @@
+use futures::TryStreamExt;
 use mongodb::{
-    bson::{doc, oid::ObjectId},
-    options::IndexOptions,
-    Collection, Database, IndexModel,
+    bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document},
+    error::{Error as MongoError, TRANSIENT_TRANSACTION_ERROR},
+    options::{IndexOptions, ReturnDocument},
+    ClientSession, Collection, Database, IndexModel,
 };
 
 use crate::{
@@ -15,49 +17,105 @@ use crate::{
 pub struct UserRepository {
     users: Collection<User>,
     refresh_tokens: Collection<RefreshToken>,
+    setup: Collection<Document>,
 }
 
 impl UserRepository {
     pub fn new(db: &Database) -> Self {
         let users = db.collection::<User>("users");
         let refresh_tokens = db.collection::<RefreshToken>("tokens");
+        let setup = db.collection::<Document>("setup");
         Self {
             users,
             refresh_tokens,
+            setup,
         }
     }
 
     pub async fn new_async(db: &Database) -> Result<Self, ApiErrorResponse> {
         let users = db.collection::<User>("users");
         let refresh_tokens = db.collection::<RefreshToken>("tokens");
+        let setup = db.collection::<Document>("setup");
         ensure_indexes(&users).await?;
         Ok(Self {
             users,
             refresh_tokens,
+            setup,
         })
     }
 
     pub async fn create_user(&self, new_user: User) -> Result<NewUser, ApiErrorResponse> {
         let result = self.users.insert_one(&new_user).await;
+        created_user(result.map(|_| new_user))
+    }
 
-        match result {
-            Ok(_) => Ok(NewUser {
-                id: new_user.id.to_string(),
-                name: new_user.name,
-                email: new_user.email,
-                phone_number: new_user.phone_number,
-                r#type: new_user.r#type,
-                created_customers: new_user.created_customers,
-                created_by: new_user.created_by,
-                spm_id: new_user.spm_id,
-                created_at: new_user.created_at,
-                updated_at: new_user.updated_at,
-            }),
+    pub async fn create_user_in_session(
+        &self,
+        session: &mut ClientSession,
+        new_user: User,
+    ) -> Result<NewUser, ApiErrorResponse> {
+        let result = self
+            .users
+            .insert_one(&new_user)
+            .session(&mut *session)
+            .await;
+        created_user(result.map(|_| new_user))
+    }
+
+    /// Records that the first super-admin has been created. The fixed `_id`
+    /// means only one caller can ever succeed.
+    pub async fn complete_super_admin_setup(
+        &self,
+        session: &mut ClientSession,
+        super_admin_id: ObjectId,
+    ) -> Result<(), ApiErrorResponse> {
+        let setup = doc! {
+            "_id": "super_admin",
+            "user_id": super_admin_id,
+            "completed_at": BsonDateTime::now(),
+        };
+        match self.setup.insert_one(setup).session(&mut *session).await {
+            Ok(_) => Ok(()),
             Err(err) if err.to_string().contains("E11000 duplicate key error") => Err(
-                ApiErrorResponse::new(400, "Email already exists".to_string()),
+                ApiErrorResponse::new(409, String::from("Setup has already been completed")),
             ),
-            Err(err) => Err(internal_error(err)),
+            Err(err) => Err(transaction_error(err)),
+        }
+    }
+
+    pub async fn is_super_admin_setup_complete(&self) -> Result<bool, ApiErrorResponse> {
+        let setup = self
+            .setup
+            .find_one(doc! { "_id": "super_admin" })
+            .await
+            .map_err(internal_error)?;
+        Ok(setup.is_some())
+    }
+
+    /// Records `customer_id` against the admin, unless they already have
+    /// `max_customers`. Returns false when the limit has been reached.
+    pub async fn push_created_customer(
+        &self,
+        session: &mut ClientSession,
+        admin_id: ObjectId,
+        customer_id: ObjectId,
+        max_customers: usize,
+    ) -> Result<bool, ApiErrorResponse> {
+        if max_customers == 0 {
+            return Ok(false);
         }
+        let filter = doc! {
+            "_id": admin_id,
+            format!("created_customers.{}", max_customers - 1): { "$exists": false },
+        };
+        let update = doc! { "$push": { "created_customers": customer_id } };
+        let result = self
+            .users
+            .update_one(filter, update)
+            .session(&mut *session)
+            .await
+            .map_err(transaction_error)?;
+        Ok(result.modified_count > 0)
     }
 
     pub async fn find_user_by_id(&self, id: &str) -> Result<Option<User>, ApiErrorResponse> {
@@ -73,6 +131,20 @@ impl UserRepository {
         Ok(user)
     }
 
+    /// Users at the top of an account, i.e. not created by another user.
+    pub async fn find_account_holders(&self) -> Result<Vec<User>, ApiErrorResponse> {
+        let users = self
+            .users
+            .find(doc! { "created_by": { "$exists": false } })
+            .await
+            .map_err(internal_error)?
+            .try_collect()
+            .await
+            .map_err(internal_error)?;
+
+        Ok(users)
+    }
+
     pub async fn find_admin_user_by_id(
         &self,
         admin_id: String,
@@ -90,6 +162,108 @@ impl UserRepository {
         Ok(admin_user)
     }
 
+    /// Users `manager` created, including customers recorded only in their
+    /// `created_customers`.
+    pub async fn find_users_managed_by(
+        &self,
+        manager: &User,
+    ) -> Result<Vec<User>, ApiErrorResponse> {
+        let created_customers = manager.created_customers.clone().unwrap_or_default();
+        let filter = doc! { "$or": [
+            { "created_by": manager.id },
+            { "_id": { "$in": created_customers } },
+        ] };
+        let users = self
+            .users
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .await
+            .map_err(internal_error)?
+            .try_collect()
+            .await
+            .map_err(internal_error)?;
+
+        Ok(users)
+    }
+
+    /// Every user whose `created_by` chain leads back to `root`: the users it
+    /// created, the users those created, and so on.
+    pub async fn find_users_created_under(
+        &self,
+        root: ObjectId,
+    ) -> Result<Vec<User>, ApiErrorResponse> {
+        let mut users: Vec<User> = Vec::new();
+        let mut creators = vec![root];
+        while !creators.is_empty() {
+            let seen: Vec<ObjectId> = users.iter().map(|user| user.id).collect();
+            let created: Vec<User> = self
+                .users
+                .find(doc! {
+                    "created_by": { "$in": &creators },
+                    "_id": { "$nin": seen },
+                })
+                .await
+                .map_err(internal_error)?
+                .try_collect()
+                .await
+                .map_err(internal_error)?;
+            creators = created.iter().map(|user| user.id).collect();
+            users.extend(created);
+        }
+
+        Ok(users)
+    }
+
+    pub async fn update_user(
+        &self,
+        id: ObjectId,
+        mut update: Document,
+    ) -> Result<Option<User>, ApiErrorResponse> {
+        update.insert("updated_at", BsonDateTime::now());
+        let result = self
+            .users
+            .find_one_and_update(doc! { "_id": id }, doc! { "$set": update })
+            .return_document(ReturnDocument::After)
+            .await;
+
+        match result {
+            Ok(user) => Ok(user),
+            Err(err) if err.to_string().contains("E11000 duplicate key error") => Err(
+                ApiErrorResponse::new(400, "Email already exists".to_string()),
+            ),
+            Err(err) => Err(internal_error(err)),
+        }
+    }
+
+    /// Removes the user, their refresh token, and their entry in any
+    /// `created_customers` list.
+    pub async fn delete_user(
+        &self,
+        session: &mut ClientSession,
+        id: ObjectId,
+    ) -> Result<(), ApiErrorResponse> {
+        self.refresh_tokens
+            .delete_many(doc! { "user_id": id })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+        self.users
+            .update_many(
+                doc! { "created_customers": id },
+                doc! { "$pull": { "created_customers": id } },
+            )
+            .session(&mut *session)
+            .await
+            .map_err(transaction_error)?;
+        self.users
+            .delete_one(doc! { "_id": id })
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(())
+    }
+
     pub async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, ApiErrorResponse> {
         let user = self
             .users
@@ -138,6 +312,29 @@ impl UserRepository {
         Ok(refresh_token)
     }
 
+    pub async fn update_user_password_in_session(
+        &self,
+        session: &mut ClientSession,
+        id: &str,
+        new_password: String,
+    ) -> Result<(), ApiErrorResponse> {
+        let user_id = ObjectId::parse_str(id).map_err(internal_error)?;
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "password": new_password }  };
+
+        let result = self
+            .users
+            .update_one(filter, update)
+            .session(&mut *session)
+            .await
+            .map_err(internal_error)?;
+
+        match result.matched_count {
+            0 => Err(ApiErrorResponse::new(404, String::from("user not found"))),
+            _ => Ok(()),
+        }
+    }
+
     pub async fn update_user_password_by_id(
         &self,
         id: &str,
@@ -178,3 +375,31 @@ pub async fn ensure_indexes(users: &Collection<User>) -> Result<(), ApiErrorResp
 
     Ok(())
 }
+
+fn created_user(result: Result<User, MongoError>) -> Result<NewUser, ApiErrorResponse> {
+    match result {
+        Ok(new_user) => Ok(NewUser::from(new_user)),
+        Err(err) if err.to_string().contains("E11000 duplicate key error") => Err(
+            ApiErrorResponse::new(400, "Email already exists".to_string()),
+        ),
+        Err(err) => Err(transaction_error(err)),
+    }
+}
+
+const WRITE_CONFLICT_MESSAGE: &str = "Another request is updating this account, please retry";
+
+/// Concurrent writers to the same admin lose with a write conflict; surface
+/// that as a retryable conflict rather than a server error.
+fn transaction_error(err: MongoError) -> ApiErrorResponse {
+    if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+        ApiErrorResponse::new(409, String::from(WRITE_CONFLICT_MESSAGE))
+    } else {
+        internal_error(err)
+    }
+}
+
+/// Whether `err` is a write conflict that is safe to retry in a new
+/// transaction.
+pub fn is_write_conflict(err: &ApiErrorResponse) -> bool {
+    err.status() == 409 && err.message() == WRITE_CONFLICT_MESSAGE
+}
@@ -0,0 +1,446 @@
+use std::{str::FromStr, sync::Arc};
+
+use bson::oid::ObjectId;
+use chrono::{DateTime, TimeDelta, Utc};
+use mongodb::Client;
+
+use crate::{
+    dtos::alert_dtos::{AddAlertCommentDto, AlertDto, AlertQuery, UserAlertsResponse},
+    models::{
+        alert::{Alert, AlertMetric, AlertSeverity, AlertState},
+        spm::{Cage, CageReading, DiseaseThreshold, HealthSettings, SensorRange},
+    },
+    notifiers::Notification,
+    repository::{alert_repository::AlertRepository, spm_repository::SpmRepository},
+    services::{
+        notification_service::NotificationService,
+        spm_service::{find_accessible_cage, find_accessible_cages},
+        telemetry_service::{CageEvent, TelemetryHub},
+    },
+    utils::{
+        error_handler::{http_error, not_found_error},
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
+};
+
+// How far past a bound, relative to the bound itself, a value must be before
+// the alert is raised as critical rather than a warning.
+const CRITICAL_DEVIATION_RATIO: f32 = 0.1;
+
+pub struct AlertService {
+    client: Arc<Client>,
+}
+
+impl AlertService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    pub async fn evaluate_reading(
+        &self,
+        cage: &Cage,
+        reading: &CageReading,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<(), ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let alert_repo = AlertRepository::new(&db);
+
+        let health_settings = match spm_repo
+            .find_health_settings_by_cage_id(&cage.cage_id)
+            .await?
+        {
+            Some(health_settings) => health_settings,
+            None => return Ok(()),
+        };
+
+        for (metric, value, range) in metric_ranges(reading, &health_settings) {
+            let active_alert = alert_repo.find_active_alert(&cage.cage_id, metric).await?;
+
+            if let Some(active_alert) = active_alert {
+                if range.is_cleared(value) {
+                    if let Some(cleared_alert) =
+                        alert_repo.clear_alert(active_alert.id, value).await?
+                    {
+                        telemetry_hub.publish(CageEvent::Alert(AlertDto::from(cleared_alert)));
+                    }
+                } else {
+                    let severity = severity_for(value, active_alert.threshold);
+                    alert_repo
+                        .update_alert_value(active_alert.id, value, severity)
+                        .await?;
+                }
+                continue;
+            }
+
+            let threshold = match range.breached_bound(value) {
+                Some(threshold) => threshold,
+                None => {
+                    alert_repo
+                        .clear_sensor_breach(&cage.cage_id, metric)
+                        .await?;
+                    continue;
+                }
+            };
+
+            let breach = alert_repo
+                .record_sensor_breach(&cage.cage_id, metric, reading.timestamp)
+                .await?;
+            // Settings saved before the duration was bounded may still hold
+            // values too large for a `TimeDelta`; those never elapse.
+            let min_duration = i64::try_from(range.min_duration_secs)
+                .ok()
+                .and_then(TimeDelta::try_seconds)
+                .unwrap_or(TimeDelta::MAX);
+            if reading.timestamp - breach.started_at < min_duration {
+                continue;
+            }
+
+            self.open_alert(
+                cage,
+                metric,
+                value,
+                threshold,
+                reading.timestamp,
+                telemetry_hub,
+            )
+            .await?;
+        }
+
+        for (metric, probability, disease_threshold) in
+            disease_thresholds(reading, &health_settings)
+        {
+            let active_alert = alert_repo.find_active_alert(&cage.cage_id, metric).await?;
+
+            if let Some(active_alert) = active_alert {
+                if disease_threshold.exceeded_threshold(probability).is_some() {
+                    let severity = severity_for(probability, active_alert.threshold);
+                    alert_repo
+                        .update_alert_value(active_alert.id, probability, severity)
+                        .await?;
+                } else if let Some(cleared_alert) =
+                    alert_repo.clear_alert(active_alert.id, probability).await?
+                {
+                    telemetry_hub.publish(CageEvent::Alert(AlertDto::from(cleared_alert)));
+                }
+                continue;
+            }
+
+            let threshold = match disease_threshold.exceeded_threshold(probability) {
+                Some(threshold) => threshold,
+                None => {
+                    alert_repo
+                        .clear_sensor_breach(&cage.cage_id, metric)
+                        .await?;
+                    continue;
+                }
+            };
+
+            let breach = alert_repo
+                .record_sensor_breach(&cage.cage_id, metric, reading.timestamp)
+                .await?;
+            if breach.readings < disease_threshold.consecutive_readings {
+                continue;
+            }
+
+            self.open_alert(
+                cage,
+                metric,
+                probability,
+                threshold,
+                reading.timestamp,
+                telemetry_hub,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_users_alerts(
+        &self,
+        user_id: String,
+        alert_query: AlertQuery,
+    ) -> Result<ApiSuccessResponse<UserAlertsResponse>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let state = alert_query
+            .state
+            .map(|state| AlertState::from_str(&state))
+            .transpose()
+            .map_err(|e| http_error(e, 400, "Invalid alert state"))?;
+        let severity = alert_query
+            .severity
+            .map(|severity| AlertSeverity::from_str(&severity))
+            .transpose()
+            .map_err(|e| http_error(e, 400, "Invalid alert severity"))?;
+
+        let cage_ids: Vec<String> = find_accessible_cages(&db, &user_id)
+            .await?
+            .into_iter()
+            .map(|cage| cage.cage_id)
+            .filter(|cage_id| {
+                alert_query
+                    .cage_id
+                    .as_ref()
+                    .is_none_or(|wanted| wanted == cage_id)
+            })
+            .collect();
+
+        let (alerts, total_alerts) = alert_repo
+            .find_alerts_with_pagination(
+                &cage_ids,
+                state,
+                severity,
+                alert_query.offset,
+                alert_query.limit,
+            )
+            .await?;
+        let user_alerts = UserAlertsResponse {
+            total_alerts,
+            alerts: alerts.into_iter().map(AlertDto::from).collect(),
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched alerts"),
+            user_alerts,
+            None,
+        ))
+    }
+
+    pub async fn get_users_alert(
+        &self,
+        user_id: String,
+        alert_id: String,
+    ) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+        let alert = self.find_users_alert(&user_id, &alert_id).await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched alert"),
+            AlertDto::from(alert),
+            None,
+        ))
+    }
+
+    pub async fn acknowledge_alert(
+        &self,
+        user_id: String,
+        alert_id: String,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let alert = self.find_users_alert(&user_id, &alert_id).await?;
+        let acknowledged_alert = alert_repo
+            .acknowledge_alert(alert.id, &user_id)
+            .await?
+            .ok_or_else(|| {
+                ApiErrorResponse::new(409, String::from("Only open alerts can be acknowledged"))
+            })?;
+        let alert_dto = AlertDto::from(acknowledged_alert);
+        telemetry_hub.publish(CageEvent::Alert(alert_dto.clone()));
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully acknowledged alert"),
+            alert_dto,
+            None,
+        ))
+    }
+
+    pub async fn resolve_alert(
+        &self,
+        user_id: String,
+        alert_id: String,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let alert = self.find_users_alert(&user_id, &alert_id).await?;
+        let resolved_alert = alert_repo
+            .resolve_alert(alert.id, &user_id)
+            .await?
+            .ok_or_else(|| ApiErrorResponse::new(409, String::from("Alert is already resolved")))?;
+        let alert_dto = AlertDto::from(resolved_alert);
+        telemetry_hub.publish(CageEvent::Alert(alert_dto.clone()));
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully resolved alert"),
+            alert_dto,
+            None,
+        ))
+    }
+
+    pub async fn add_alert_comment(
+        &self,
+        user_id: String,
+        alert_id: String,
+        add_alert_comment_dto: AddAlertCommentDto,
+    ) -> Result<ApiSuccessResponse<AlertDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let alert = self.find_users_alert(&user_id, &alert_id).await?;
+        let comment = add_alert_comment_dto.to_model(user_id);
+        let commented_alert = alert_repo
+            .add_alert_comment(alert.id, comment)
+            .await?
+            .ok_or_else(|| not_found_error((), "Alert does not exist"))?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully added alert comment"),
+            AlertDto::from(commented_alert),
+            None,
+        ))
+    }
+
+    async fn open_alert(
+        &self,
+        cage: &Cage,
+        metric: AlertMetric,
+        value: f32,
+        threshold: f32,
+        opened_at: DateTime<Utc>,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<(), ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let created_alert = alert_repo
+            .create_alert(Alert {
+                id: ObjectId::new(),
+                cage_id: cage.cage_id.clone(),
+                assigned_monitor: cage.assigned_monitor.clone(),
+                metric,
+                severity: severity_for(value, threshold),
+                state: AlertState::Open,
+                active: true,
+                threshold,
+                trigger_value: value,
+                last_value: value,
+                opened_at,
+                cleared_at: None,
+                acknowledged_by: None,
+                acknowledged_at: None,
+                resolved_by: None,
+                resolved_at: None,
+                comments: vec![],
+                updated_at: Utc::now(),
+            })
+            .await?;
+        alert_repo
+            .clear_sensor_breach(&cage.cage_id, metric)
+            .await?;
+        // Another reading opened it first; that one notifies.
+        let Some(alert) = created_alert else {
+            return Ok(());
+        };
+
+        let notification = Notification {
+            event: String::from("alert.opened"),
+            subject: format!(
+                "{} {} alert on cage {}",
+                alert.severity, metric, cage.cage_id
+            ),
+            body: format!(
+                "{} reading of {} on cage {} crossed the threshold of {}.",
+                metric, value, cage.cage_id, threshold
+            ),
+            sensitive: false,
+        };
+        // A notification that can't be queued shouldn't undo the alert itself.
+        if let Err(err) = NotificationService::new(self.client.clone())
+            .notify_user(&cage.assigned_monitor, notification)
+            .await
+        {
+            tracing::error!("failed to queue alert notification: {:?}", err);
+        }
+        telemetry_hub.publish(CageEvent::Alert(AlertDto::from(alert)));
+
+        Ok(())
+    }
+
+    async fn find_users_alert(
+        &self,
+        user_id: &str,
+        alert_id: &str,
+    ) -> Result<Alert, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let alert_repo = AlertRepository::new(&db);
+
+        let alert = match alert_repo.find_alert_by_id(alert_id).await? {
+            Some(alert) => alert,
+            None => return Err(not_found_error((), "Alert does not exist")),
+        };
+
+        // Access follows the cage, so alerts move with a reassigned cage.
+        match find_accessible_cage(&db, user_id, &alert.cage_id).await {
+            Ok(_) => Ok(alert),
+            Err(err) if err.status() == 404 => Err(not_found_error((), "Alert does not exist")),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn severity_for(value: f32, threshold: f32) -> AlertSeverity {
+    if (value - threshold).abs() > threshold.abs() * CRITICAL_DEVIATION_RATIO {
+        AlertSeverity::Critical
+    } else {
+        AlertSeverity::Warning
+    }
+}
+
+fn metric_ranges<'a>(
+    reading: &CageReading,
+    health_settings: &'a HealthSettings,
+) -> [(AlertMetric, f32, &'a SensorRange); 5] {
+    [
+        (
+            AlertMetric::Temperature,
+            reading.temperature,
+            &health_settings.temperature,
+        ),
+        (
+            AlertMetric::Pressure,
+            reading.pressure,
+            &health_settings.pressure,
+        ),
+        (
+            AlertMetric::Humidity,
+            reading.humidity,
+            &health_settings.humidity,
+        ),
+        (
+            AlertMetric::Ammonia,
+            reading.ammonia,
+            &health_settings.ammonia,
+        ),
+        (AlertMetric::Co2, reading.co2, &health_settings.co2),
+    ]
+}
+
+fn disease_thresholds<'a>(
+    reading: &CageReading,
+    health_settings: &'a HealthSettings,
+) -> [(AlertMetric, f32, &'a DiseaseThreshold); 3] {
+    let object_recognition = &reading.object_recognition;
+    [
+        (
+            AlertMetric::Coccidiosis,
+            object_recognition.coccidiosis,
+            &health_settings.coccidiosis,
+        ),
+        (
+            AlertMetric::Newcastle,
+            object_recognition.newcastle,
+            &health_settings.newcastle,
+        ),
+        (
+            AlertMetric::Salmonella,
+            object_recognition.salmonella,
+            &health_settings.salmonella,
+        ),
+    ]
+}
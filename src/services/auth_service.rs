@@ -69,6 +69,13 @@ impl AuthService {
             ));
         }
 
+        if found_user.disabled {
+            return Err(ApiErrorResponse::new(
+                403,
+                String::from("Account is disabled"),
+            ));
+        }
+
         let user_id = found_user.id;
         let access_token = jwt::new(user_id.clone().to_string(), found_user.r#type)
             .map_err(invalid_credentials_error)?;
@@ -139,6 +146,12 @@ impl AuthService {
                 .find_user_by_id(&valid_refresh_token.user_id.to_string())
                 .await?
             {
+                Some(user) if user.disabled => {
+                    return Err(ApiErrorResponse::new(
+                        403,
+                        String::from("Account is disabled"),
+                    ))
+                }
                 Some(user) => user,
                 None => {
                     return Err(ApiErrorResponse::new(
@@ -215,18 +228,7 @@ impl AuthService {
 
         match user {
             Some(found_user) => {
-                let user = NewUser {
-                    id: found_user.id.to_string(),
-                    name: found_user.name,
-                    r#type: found_user.r#type,
-                    email: found_user.email,
-                    spm_id: found_user.spm_id,
-                    created_by: found_user.created_by,
-                    phone_number: found_user.phone_number,
-                    created_customers: found_user.created_customers,
-                    created_at: found_user.created_at,
-                    updated_at: found_user.updated_at,
-                };
+                let user = NewUser::from(found_user);
 
                 Ok(ApiSuccessResponse::new(
                     String::from("Succesfully fetched authenticated user"),
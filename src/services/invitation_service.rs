@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use bcrypt::hash;
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use mongodb::{Client, ClientSession};
+
+use crate::{
+    dtos::invitation_dtos::{AcceptInvitationDto, InvitationDto},
+    models::{invitation::Invitation, notification::ChannelKind, user::NewUser},
+    notifiers::Notification,
+    repository::{invitation_repository::InvitationRepository, user_repository::UserRepository},
+    services::notification_service::NotificationService,
+    utils::{
+        error_handler::{http_error, internal_error, not_found_error},
+        helper::{generate_password, hash_id_with_secret},
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
+};
+
+/// How long an invite token can be redeemed for after it is (re)sent.
+const INVITATION_TTL: Duration = Duration::hours(72);
+
+pub struct InvitationService {
+    client: Arc<Client>,
+}
+
+impl InvitationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    /// Records an invitation for a user created in the same transaction. The
+    /// returned token is emailed with `notify_invited_user` after the commit.
+    pub async fn invite_user(
+        &self,
+        session: &mut ClientSession,
+        invited_by: &str,
+        user: &NewUser,
+    ) -> Result<(Invitation, String), ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let invitation_repo = InvitationRepository::new(&db);
+
+        let token = generate_password(32);
+        let now = Utc::now();
+        let invitation = invitation_repo
+            .create_invitation(
+                session,
+                Invitation {
+                    id: ObjectId::new(),
+                    user_id: user.id.clone(),
+                    email: user.email.clone(),
+                    invited_by: invited_by.to_string(),
+                    token_hash: hash_id_with_secret(&token),
+                    expires_at: now + INVITATION_TTL,
+                    accepted_at: None,
+                    revoked_at: None,
+                    created_at: now,
+                    updated_at: now,
+                },
+            )
+            .await?;
+
+        Ok((invitation, token))
+    }
+
+    /// The user already exists by now, so a failure to queue the email is
+    /// logged rather than returned; the invitation can be resent.
+    pub async fn notify_invited_user(&self, user: &NewUser, invitation: &Invitation, token: &str) {
+        if let Err(err) = self.send_invitation(&user.name, invitation, token).await {
+            tracing::error!("failed to send invitation {}: {:?}", invitation.id, err);
+        }
+    }
+
+    pub async fn fetch_pending_invitations(
+        &self,
+        admin_id: String,
+    ) -> Result<ApiSuccessResponse<Vec<InvitationDto>>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let invitation_repo = InvitationRepository::new(&db);
+
+        let now = Utc::now();
+        let invitations = invitation_repo
+            .find_open_invitations_by_inviter(&admin_id)
+            .await?
+            .into_iter()
+            .map(|invitation| {
+                let status = invitation.status(now);
+                InvitationDto::from_invitation(invitation, status)
+            })
+            .collect();
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched pending invitations"),
+            invitations,
+            None,
+        ))
+    }
+
+    pub async fn resend_invitation(
+        &self,
+        admin_id: String,
+        invitation_id: String,
+    ) -> Result<ApiSuccessResponse<InvitationDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let invitation_repo = InvitationRepository::new(&db);
+        let user_repo = UserRepository::new(&db);
+
+        let invitation = self
+            .find_inviters_invitation(&invitation_repo, &admin_id, &invitation_id)
+            .await?;
+
+        let token = generate_password(32);
+        let invitation = invitation_repo
+            .reissue_invitation(
+                invitation.id,
+                &hash_id_with_secret(&token),
+                Utc::now() + INVITATION_TTL,
+            )
+            .await?
+            .ok_or_else(|| http_error((), 409, "Invitation is no longer pending"))?;
+
+        let name = user_repo
+            .find_user_by_id(&invitation.user_id)
+            .await?
+            .map(|user| user.name)
+            .unwrap_or_default();
+        self.send_invitation(&name, &invitation, &token).await?;
+
+        let status = invitation.status(Utc::now());
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully resent invitation"),
+            InvitationDto::from_invitation(invitation, status),
+            None,
+        ))
+    }
+
+    pub async fn revoke_invitation(
+        &self,
+        admin_id: String,
+        invitation_id: String,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let invitation_repo = InvitationRepository::new(&db);
+
+        let invitation = self
+            .find_inviters_invitation(&invitation_repo, &admin_id, &invitation_id)
+            .await?;
+        if !invitation_repo.revoke_invitation(invitation.id).await? {
+            return Err(http_error((), 409, "Invitation is no longer pending"));
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully revoked invitation"),
+            (),
+            None,
+        ))
+    }
+
+    pub async fn accept_invitation(
+        &self,
+        payload: AcceptInvitationDto,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let invitation_repo = InvitationRepository::new(&db);
+        let user_repo = UserRepository::new(&db);
+
+        // Hash before redeeming so a hashing failure doesn't burn the token.
+        let new_password = hash(payload.password, 12).map_err(internal_error)?;
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        // The token is only spent if the password is set too.
+        let accept_result = async {
+            let invitation = invitation_repo
+                .accept_invitation(&mut session, &hash_id_with_secret(payload.token.trim()))
+                .await?
+                .ok_or_else(|| http_error((), 410, "Invitation is invalid or has expired"))?;
+            user_repo
+                .update_user_password_in_session(&mut session, &invitation.user_id, new_password)
+                .await
+        }
+        .await;
+
+        match accept_result {
+            Ok(()) => session.commit_transaction().await.map_err(internal_error)?,
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully accepted invitation"),
+            (),
+            None,
+        ))
+    }
+
+    async fn find_inviters_invitation(
+        &self,
+        invitation_repo: &InvitationRepository,
+        admin_id: &str,
+        invitation_id: &str,
+    ) -> Result<Invitation, ApiErrorResponse> {
+        let invitation_id = ObjectId::parse_str(invitation_id)
+            .map_err(|_| not_found_error((), "Invitation does not exist"))?;
+        invitation_repo
+            .find_invitation_by_id(invitation_id)
+            .await?
+            .filter(|invitation| invitation.invited_by == admin_id)
+            .ok_or_else(|| not_found_error((), "Invitation does not exist"))
+    }
+
+    async fn send_invitation(
+        &self,
+        name: &str,
+        invitation: &Invitation,
+        token: &str,
+    ) -> Result<(), ApiErrorResponse> {
+        let notification = Notification {
+            event: String::from("user.invited"),
+            subject: String::from("You've been invited to Fiya"),
+            body: format!(
+                "Hello {},\n\nAn account has been created for you on Fiya.\n\n\
+                 Use this invite code to set your password: {}\n\n\
+                 The code can only be used once and expires on {}.",
+                name,
+                token,
+                invitation.expires_at.to_rfc3339()
+            ),
+            sensitive: true,
+        };
+        NotificationService::new(self.client.clone())
+            .notify_address(ChannelKind::Email, invitation.email.clone(), notification)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::database::test_mongodb_connection, migrations::run_migrations,
+        utils::helper::tests::set_test_spm_secret,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB replica set at TEST_DATABASE_URL"]
+    async fn failed_password_update_leaves_the_invitation_open() {
+        set_test_spm_secret();
+        let client = Arc::new(test_mongodb_connection().await);
+        run_migrations(&client).await.unwrap();
+        let invitation_repo = InvitationRepository::new(&client.database("fiyadb"));
+
+        // The invited user is gone, so setting the password fails.
+        let token = generate_password(32);
+        let now = Utc::now();
+        let mut session = client.start_session().await.unwrap();
+        let invitation = invitation_repo
+            .create_invitation(
+                &mut session,
+                Invitation {
+                    id: ObjectId::new(),
+                    user_id: ObjectId::new().to_hex(),
+                    email: format!("{}@example.com", ObjectId::new()),
+                    invited_by: ObjectId::new().to_hex(),
+                    token_hash: hash_id_with_secret(&token),
+                    expires_at: now + INVITATION_TTL,
+                    accepted_at: None,
+                    revoked_at: None,
+                    created_at: now,
+                    updated_at: now,
+                },
+            )
+            .await
+            .unwrap();
+
+        let err = InvitationService::new(client.clone())
+            .accept_invitation(AcceptInvitationDto {
+                token,
+                password: String::from("a-new-password"),
+            })
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), 404);
+
+        let stored = invitation_repo
+            .find_invitation_by_id(invitation.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(stored.accepted_at.is_none());
+    }
+}
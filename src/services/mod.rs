@@ -1,3 +1,9 @@
+pub mod alert_service;
 pub mod auth_service;
+pub mod invitation_service;
+pub mod notification_service;
+pub mod plan_service;
+pub mod provisioning_service;
 pub mod spm_service;
+pub mod telemetry_service;
 pub mod user_service;
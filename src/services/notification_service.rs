@@ -0,0 +1,290 @@
+use std::{str::FromStr, sync::Arc, time::Duration as StdDuration};
+
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use mongodb::Client;
+use validator::ValidateEmail;
+
+use crate::{
+    dtos::notification_dtos::{CreateNotificationChannelDto, NotificationChannelDto},
+    models::notification::{
+        ChannelKind, DeliveryStatus, NotificationChannel, NotificationDelivery,
+    },
+    notifiers::{webhook::ensure_public_webhook_url, Notification, Notifiers, Recipient},
+    repository::notification_repository::NotificationRepository,
+    utils::{
+        error_handler::{http_error, not_found_error},
+        helper::generate_password,
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
+};
+
+const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+const DELIVERY_LEASE: Duration = Duration::minutes(2);
+const DELIVERY_POLL_INTERVAL: StdDuration = StdDuration::from_secs(5);
+
+pub struct NotificationService {
+    client: Arc<Client>,
+}
+
+impl NotificationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    pub async fn create_channel(
+        &self,
+        user_id: String,
+        payload: CreateNotificationChannelDto,
+    ) -> Result<ApiSuccessResponse<NotificationChannelDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        let kind = ChannelKind::from_str(&payload.kind)
+            .map_err(|e| http_error(e, 400, "Invalid channel kind"))?;
+        let valid_address = match kind {
+            // Webhooks are called from inside our network, so they must not
+            // point at private or loopback addresses.
+            ChannelKind::Webhook => ensure_public_webhook_url(&payload.address).await.is_ok(),
+            ChannelKind::Email => payload.address.validate_email(),
+            ChannelKind::Sms => payload
+                .address
+                .trim_start_matches('+')
+                .chars()
+                .all(|c| c.is_ascii_digit()),
+        };
+        if !valid_address {
+            return Err(ApiErrorResponse::new(
+                400,
+                format!("Invalid address for {kind} channel"),
+            ));
+        }
+
+        // Webhook receivers need a secret to verify signatures, so generate one
+        // when the caller doesn't bring their own.
+        let secret = match kind {
+            ChannelKind::Webhook => Some(payload.secret.unwrap_or_else(|| generate_password(32))),
+            _ => None,
+        };
+
+        let channel = notification_repo
+            .create_channel(NotificationChannel {
+                id: ObjectId::new(),
+                user_id,
+                kind,
+                address: payload.address,
+                secret: secret.clone(),
+                enabled: true,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            })
+            .await?;
+
+        let mut channel_dto = NotificationChannelDto::from(channel);
+        channel_dto.secret = secret;
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully created notification channel"),
+            channel_dto,
+            None,
+        ))
+    }
+
+    pub async fn fetch_users_channels(
+        &self,
+        user_id: String,
+    ) -> Result<ApiSuccessResponse<Vec<NotificationChannelDto>>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        let channels = notification_repo.find_channels_by_user_id(&user_id).await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched notification channels"),
+            channels
+                .into_iter()
+                .map(NotificationChannelDto::from)
+                .collect(),
+            None,
+        ))
+    }
+
+    pub async fn delete_channel(
+        &self,
+        user_id: String,
+        channel_id: String,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        let channel_id = ObjectId::parse_str(&channel_id)
+            .map_err(|e| http_error(e, 400, "Invalid channel id"))?;
+        if !notification_repo
+            .delete_channel(&user_id, channel_id)
+            .await?
+        {
+            return Err(not_found_error((), "Notification channel does not exist"));
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully deleted notification channel"),
+            (),
+            None,
+        ))
+    }
+
+    /// Queues a notification on every enabled channel of the user.
+    pub async fn notify_user(
+        &self,
+        user_id: &str,
+        notification: Notification,
+    ) -> Result<(), ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        let deliveries = notification_repo
+            .find_enabled_channels_by_user_id(user_id)
+            .await?
+            .into_iter()
+            .map(|channel| {
+                new_delivery(
+                    Some(user_id.to_string()),
+                    channel.kind,
+                    channel.address,
+                    channel.secret,
+                    &notification,
+                )
+            })
+            .collect();
+        notification_repo.enqueue_deliveries(deliveries).await
+    }
+
+    /// Queues a notification to an address that isn't a configured channel,
+    /// e.g. a user who can't sign in yet.
+    pub async fn notify_address(
+        &self,
+        kind: ChannelKind,
+        address: String,
+        notification: Notification,
+    ) -> Result<(), ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        let delivery = new_delivery(None, kind, address, None, &notification);
+        notification_repo.enqueue_deliveries(vec![delivery]).await
+    }
+
+    pub async fn run_delivery_worker(self, notifiers: Notifiers) {
+        let db = self.client.database("fiyadb");
+        let notification_repo = NotificationRepository::new(&db);
+
+        loop {
+            let claimed = notification_repo
+                .claim_due_delivery(Utc::now() + DELIVERY_LEASE)
+                .await;
+            let delivery = match claimed {
+                Ok(Some(delivery)) => delivery,
+                Ok(None) => {
+                    tokio::time::sleep(DELIVERY_POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!("failed to claim notification delivery: {:?}", err);
+                    tokio::time::sleep(DELIVERY_POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+
+            let recipient = Recipient {
+                address: &delivery.address,
+                secret: delivery.secret.as_deref(),
+            };
+            let notification = Notification {
+                event: delivery.event.clone(),
+                subject: delivery.subject.clone(),
+                body: delivery.body.clone(),
+                sensitive: delivery.sensitive,
+            };
+            let sent = match notifiers.for_kind(delivery.kind) {
+                Ok(notifier) => notifier.send(&recipient, &notification).await,
+                Err(err) => Err(err),
+            };
+
+            let result = match sent {
+                Ok(()) => {
+                    notification_repo
+                        .mark_delivered(delivery.id, delivery.sensitive)
+                        .await
+                }
+                Err(err) => {
+                    let next_attempt_at = (delivery.attempts < MAX_DELIVERY_ATTEMPTS)
+                        .then(|| Utc::now() + retry_backoff(delivery.attempts));
+                    tracing::warn!(
+                        "notification delivery {} attempt {} failed: {err}",
+                        delivery.id,
+                        delivery.attempts
+                    );
+                    // A sensitive body is only kept while it may still be sent.
+                    let redact_body = delivery.sensitive && next_attempt_at.is_none();
+                    notification_repo
+                        .mark_attempt_failed(
+                            delivery.id,
+                            err.to_string(),
+                            next_attempt_at,
+                            redact_body,
+                        )
+                        .await
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!("failed to record notification delivery: {:?}", err);
+            }
+        }
+    }
+}
+
+fn new_delivery(
+    user_id: Option<String>,
+    kind: ChannelKind,
+    address: String,
+    secret: Option<String>,
+    notification: &Notification,
+) -> NotificationDelivery {
+    NotificationDelivery {
+        id: ObjectId::new(),
+        user_id,
+        kind,
+        address,
+        secret,
+        event: notification.event.clone(),
+        subject: notification.subject.clone(),
+        body: notification.body.clone(),
+        sensitive: notification.sensitive,
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        next_attempt_at: Utc::now(),
+        last_error: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+fn retry_backoff(attempts: u32) -> Duration {
+    let seconds = 30_i64 << attempts.saturating_sub(1).min(7);
+    Duration::seconds(seconds.min(3600))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_backoff_doubles_up_to_an_hour() {
+        assert_eq!(retry_backoff(0), Duration::seconds(30));
+        assert_eq!(retry_backoff(1), Duration::seconds(30));
+        assert_eq!(retry_backoff(2), Duration::seconds(60));
+        assert_eq!(retry_backoff(3), Duration::seconds(120));
+        assert_eq!(retry_backoff(7), Duration::seconds(1920));
+        assert_eq!(retry_backoff(8), Duration::seconds(3600));
+        assert_eq!(retry_backoff(u32::MAX), Duration::seconds(3600));
+    }
+}
@@ -0,0 +1,334 @@
+use std::{collections::HashSet, sync::Arc, time::Duration as StdDuration};
+
+use bson::oid::ObjectId;
+use chrono::{DateTime, Duration, Utc};
+use mongodb::{Client, Database};
+
+use crate::{
+    dtos::plan_dtos::{PlanDto, UpdatePlanDto},
+    models::{
+        plan::{ExportFormat, Plan},
+        user::User,
+    },
+    repository::{
+        plan_repository::PlanRepository, spm_repository::SpmRepository,
+        user_repository::UserRepository,
+    },
+    utils::{
+        error_handler::{http_error, not_found_error},
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
+};
+
+const RETENTION_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+// Accounts are at most admin -> customer -> staff deep.
+const MAX_ACCOUNT_DEPTH: usize = 3;
+
+pub struct PlanService {
+    client: Arc<Client>,
+}
+
+impl PlanService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    pub async fn fetch_plan(
+        &self,
+        admin_id: String,
+    ) -> Result<ApiSuccessResponse<PlanDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let admin = find_account_holder(&db, &admin_id).await?;
+        let plan = find_account_plan(&db, &admin).await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched plan"),
+            PlanDto::from(plan),
+            None,
+        ))
+    }
+
+    pub async fn update_plan(
+        &self,
+        admin_id: String,
+        payload: UpdatePlanDto,
+    ) -> Result<ApiSuccessResponse<PlanDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let plan_repo = PlanRepository::new(&db);
+        let admin = find_account_holder(&db, &admin_id).await?;
+
+        let plan = plan_repo
+            .upsert_plan(&Plan {
+                name: payload.name,
+                max_customers: payload.max_customers,
+                max_cages: payload.max_cages,
+                retention_days: payload.retention_days,
+                export_formats: payload.export_formats,
+                ..Plan::default_for(admin.id)
+            })
+            .await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully updated plan"),
+            PlanDto::from(plan),
+            None,
+        ))
+    }
+
+    /// Deletes readings older than each account's retention period.
+    pub async fn run_retention_pruner(self) {
+        let db = self.client.database("fiyadb");
+        let user_repo = UserRepository::new(&db);
+        let spm_repo = SpmRepository::new(&db);
+
+        loop {
+            tokio::time::sleep(RETENTION_CHECK_INTERVAL).await;
+
+            let account_holders = match user_repo.find_account_holders().await {
+                Ok(account_holders) => account_holders,
+                Err(err) => {
+                    tracing::error!("failed to load accounts for retention: {:?}", err);
+                    continue;
+                }
+            };
+
+            for admin in account_holders {
+                let pruned = async {
+                    let plan = find_account_plan(&db, &admin).await?;
+                    let members = find_account_members(&db, &admin).await?;
+                    let cage_ids = spm_repo
+                        .find_cage_ids_by_assigned_monitors(&members)
+                        .await?;
+                    let Some(cutoff) = retention_cutoff(Utc::now(), plan.retention_days) else {
+                        return Ok(0);
+                    };
+                    spm_repo
+                        .delete_cage_readings_before(&cage_ids, cutoff)
+                        .await
+                }
+                .await;
+
+                match pruned {
+                    Ok(0) => {}
+                    Ok(deleted) => {
+                        tracing::info!("pruned {} readings for account {}", deleted, admin.id)
+                    }
+                    Err(err) => {
+                        tracing::error!("failed to prune readings for {}: {:?}", admin.id, err)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Readings recorded before the returned instant are past retention, or
+/// `None` when the retention period reaches back further than can be
+/// represented and nothing is old enough to prune.
+fn retention_cutoff(now: DateTime<Utc>, retention_days: u32) -> Option<DateTime<Utc>> {
+    now.checked_sub_signed(Duration::try_days(retention_days.into())?)
+}
+
+/// The plan covering `user`: their own if they hold the account, otherwise
+/// that of the admin they were created under.
+pub async fn find_account_plan(db: &Database, user: &User) -> Result<Plan, ApiErrorResponse> {
+    let admin = find_account_admin(db, user).await?;
+    let plan = PlanRepository::new(db)
+        .find_plan_by_admin_id(admin.id)
+        .await?;
+
+    Ok(plan.unwrap_or_else(|| Plan::default_for(admin.id)))
+}
+
+/// Fails unless the account `user` belongs to has room for `additional`
+/// more cages.
+pub async fn ensure_cage_quota(
+    db: &Database,
+    user: &User,
+    additional: usize,
+) -> Result<(), ApiErrorResponse> {
+    let admin = find_account_admin(db, user).await?;
+    let plan = find_account_plan(db, &admin).await?;
+    let members = find_account_members(db, &admin).await?;
+    let cage_count = SpmRepository::new(db)
+        .count_cages_by_assigned_monitors(&members)
+        .await?;
+
+    if cage_count + additional as u64 > u64::from(plan.max_cages) {
+        return Err(ApiErrorResponse::new(
+            409,
+            format!("Your plan allows at most {} cages", plan.max_cages),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn ensure_export_format(
+    db: &Database,
+    user: &User,
+    format: ExportFormat,
+) -> Result<(), ApiErrorResponse> {
+    let plan = find_account_plan(db, user).await?;
+    if !plan.export_formats.contains(&format) {
+        return Err(http_error(
+            (),
+            403,
+            &format!("Your plan does not include {} exports", format),
+        ));
+    }
+    Ok(())
+}
+
+async fn find_account_admin(db: &Database, user: &User) -> Result<User, ApiErrorResponse> {
+    let user_repo = UserRepository::new(db);
+
+    let mut account_admin = user.clone();
+    for _ in 0..MAX_ACCOUNT_DEPTH {
+        let Some(created_by) = account_admin.created_by else {
+            break;
+        };
+        match user_repo.find_user_by_id(&created_by.to_string()).await? {
+            Some(creator) => account_admin = creator,
+            None => break,
+        }
+    }
+    Ok(account_admin)
+}
+
+async fn find_account_holder(db: &Database, admin_id: &str) -> Result<User, ApiErrorResponse> {
+    let not_found = || not_found_error((), "Admin does not exist");
+    let user_id = ObjectId::parse_str(admin_id).map_err(|_| not_found())?;
+
+    UserRepository::new(db)
+        .find_user_by_id(&user_id.to_string())
+        .await?
+        .filter(|user| user.created_by.is_none())
+        .ok_or_else(not_found)
+}
+
+/// The admin, every customer they created and all the staff below them;
+/// cages monitored by any of them count against the admin's plan.
+async fn find_account_members(
+    db: &Database,
+    admin: &User,
+) -> Result<Vec<String>, ApiErrorResponse> {
+    let users_below = UserRepository::new(db)
+        .find_users_created_under(admin.id)
+        .await?;
+    Ok(account_members(admin, &users_below))
+}
+
+fn account_members(admin: &User, users_below: &[User]) -> Vec<String> {
+    let members: HashSet<ObjectId> = std::iter::once(admin.id)
+        .chain(admin.created_customers.iter().flatten().copied())
+        .chain(users_below.iter().map(|user| user.id))
+        .collect();
+    members.into_iter().map(|id| id.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::database::test_mongodb_connection,
+        dtos::spm_dtos::ReassignCageDto,
+        models::{spm::Cage, user::UserType},
+        services::spm_service::SpmService,
+    };
+
+    fn user(user_type: UserType, created_by: Option<&User>) -> User {
+        User {
+            id: ObjectId::new(),
+            name: String::from("Test User"),
+            email: format!("{}@example.com", ObjectId::new()),
+            phone_number: String::from("+2348012345678"),
+            password: String::new(),
+            r#type: user_type.to_string(),
+            created_customers: None,
+            created_by: created_by.map(|creator| creator.id),
+            spm_id: None,
+            disabled: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn staff_count_towards_the_account() {
+        let mut admin = user(UserType::Admin, None);
+        let owner = user(UserType::Owner, Some(&admin));
+        admin.created_customers = Some(vec![owner.id]);
+        let admins_worker = user(UserType::Worker, Some(&admin));
+        let owners_worker = user(UserType::Worker, Some(&owner));
+
+        let members = account_members(
+            &admin,
+            &[owner.clone(), admins_worker.clone(), owners_worker.clone()],
+        );
+        assert_eq!(members.len(), 4);
+        for member in [&admin, &owner, &admins_worker, &owners_worker] {
+            assert!(members.contains(&member.id.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB server at TEST_DATABASE_URL"]
+    async fn reassigning_a_cage_to_staff_keeps_it_in_the_quota() {
+        let client = Arc::new(test_mongodb_connection().await);
+        let db = client.database("fiyadb");
+
+        let admin = user(UserType::Admin, None);
+        let owner = user(UserType::Owner, Some(&admin));
+        let worker = user(UserType::Worker, Some(&owner));
+        db.collection::<User>("users")
+            .insert_many([&admin, &owner, &worker])
+            .await
+            .unwrap();
+        PlanRepository::new(&db)
+            .upsert_plan(&Plan {
+                max_cages: 1,
+                ..Plan::default_for(admin.id)
+            })
+            .await
+            .unwrap();
+        let cage = Cage {
+            id: ObjectId::new(),
+            cage_id: format!("test-{}", ObjectId::new()),
+            assigned_monitor: owner.id.to_string(),
+            livestock_no: 10,
+            archived_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        db.collection::<Cage>("cages")
+            .insert_one(&cage)
+            .await
+            .unwrap();
+
+        SpmService::new(client.clone())
+            .reassign_users_cage(
+                owner.id.to_string(),
+                cage,
+                ReassignCageDto {
+                    assigned_monitor: worker.id.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let err = ensure_cage_quota(&db, &owner, 1).await.unwrap_err();
+        assert_eq!(err.status(), 409);
+    }
+
+    #[test]
+    fn retention_cutoff_counts_back_from_now() {
+        let now = Utc::now();
+        assert_eq!(retention_cutoff(now, 30), Some(now - Duration::days(30)));
+    }
+
+    #[test]
+    fn retention_cutoff_does_not_overflow() {
+        assert_eq!(retention_cutoff(Utc::now(), u32::MAX), None);
+    }
+}
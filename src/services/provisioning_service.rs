@@ -0,0 +1,264 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use bson::oid::ObjectId;
+use chrono::Utc;
+use mongodb::Client;
+use tokio::time::{sleep, Instant};
+
+use crate::{
+    dtos::spm_dtos::{
+        ClaimDeviceDto, ClaimedDeviceDto, ProvisionDeviceDto, ProvisionDeviceResponse,
+        ProvisionStatus, RegisterDevicesDto, RegisteredDeviceDto,
+    },
+    models::{
+        provisioning::{ClaimStatus, DeviceClaim},
+        spm::Cage,
+    },
+    repository::{
+        provisioning_repository::ProvisioningRepository, spm_repository::SpmRepository,
+        user_repository::UserRepository,
+    },
+    services::{
+        plan_service::ensure_cage_quota,
+        spm_service::{issue_device_token, unseal_current_device_token},
+    },
+    utils::{
+        error_handler::{internal_error, internal_server_error, not_found_error},
+        helper::{generate_claim_code, hash_id_with_secret, normalize_claim_code},
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
+};
+
+// How long a bootstrapping device is held before being told to ask again.
+const PROVISION_WAIT: Duration = Duration::from_secs(25);
+const PROVISION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct ProvisioningService {
+    client: Arc<Client>,
+}
+
+impl ProvisioningService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    pub async fn register_devices(
+        &self,
+        user_id: String,
+        register_devices: RegisterDevicesDto,
+    ) -> Result<ApiSuccessResponse<Vec<RegisteredDeviceDto>>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let provisioning_repo = ProvisioningRepository::new(&db);
+
+        let mut seen = HashSet::new();
+        for serial_number in &register_devices.serial_numbers {
+            if serial_number.trim().is_empty() {
+                return Err(ApiErrorResponse::new(
+                    400,
+                    String::from("serial numbers must not be empty"),
+                ));
+            }
+            if !seen.insert(serial_number.trim()) {
+                return Err(ApiErrorResponse::new(
+                    400,
+                    format!(
+                        "serial number {} is listed more than once",
+                        serial_number.trim()
+                    ),
+                ));
+            }
+        }
+
+        let now = Utc::now();
+        let mut registered_devices = Vec::with_capacity(register_devices.serial_numbers.len());
+        let mut device_claims = Vec::with_capacity(register_devices.serial_numbers.len());
+        for serial_number in register_devices.serial_numbers {
+            let serial_number = serial_number.trim().to_string();
+            let claim_code = generate_claim_code();
+            device_claims.push(DeviceClaim {
+                id: ObjectId::new(),
+                serial_number: serial_number.clone(),
+                claim_code_hash: hash_id_with_secret(&normalize_claim_code(&claim_code)),
+                status: ClaimStatus::Unclaimed,
+                registered_by: user_id.clone(),
+                cage_id: None,
+                claimed_by: None,
+                claimed_at: None,
+                delivered_at: None,
+                created_at: now,
+                updated_at: now,
+            });
+            registered_devices.push(RegisteredDeviceDto {
+                serial_number,
+                claim_code,
+            });
+        }
+
+        provisioning_repo
+            .create_device_claims(&device_claims)
+            .await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully registered devices"),
+            registered_devices,
+            None,
+        ))
+    }
+
+    pub async fn claim_device(
+        &self,
+        user_id: String,
+        claim_device: ClaimDeviceDto,
+    ) -> Result<ApiSuccessResponse<ClaimedDeviceDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let provisioning_repo = ProvisioningRepository::new(&db);
+        let spm_repo = SpmRepository::new(&db);
+        let user_repo = UserRepository::new(&db);
+
+        let found_user = match user_repo.find_user_by_id(&user_id).await? {
+            Some(user) => user,
+            None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
+        };
+
+        let claim_code_hash = hash_id_with_secret(&normalize_claim_code(&claim_device.claim_code));
+        let device_claim = match provisioning_repo
+            .find_device_claim_by_code_hash(&claim_code_hash)
+            .await?
+        {
+            Some(device_claim) if device_claim.status == ClaimStatus::Unclaimed => device_claim,
+            Some(_) => {
+                return Err(ApiErrorResponse::new(
+                    409,
+                    String::from("Device has already been claimed"),
+                ))
+            }
+            None => return Err(not_found_error((), "Claim code does not exist")),
+        };
+        ensure_cage_quota(&db, &found_user, 1).await?;
+
+        let now = Utc::now();
+        let cage = Cage {
+            id: ObjectId::new(),
+            cage_id: claim_device.cage_id,
+            assigned_monitor: user_id.clone(),
+            livestock_no: claim_device.livestock_no,
+            archived_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        // The device collects its token later through the bootstrap endpoint,
+        // recovered from the sealed copy, so the plaintext is dropped here.
+        let (_, spm_device_token) = issue_device_token(&cage.cage_id);
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let claim_result = async {
+            let cage = spm_repo
+                .create_new_cage(&mut session, cage, spm_device_token)
+                .await?;
+            let claimed = provisioning_repo
+                .mark_device_claimed(&mut session, device_claim.id, &cage.cage_id, &user_id)
+                .await?;
+            if !claimed {
+                return Err(ApiErrorResponse::new(
+                    409,
+                    String::from("Device has already been claimed"),
+                ));
+            }
+            Ok(cage)
+        }
+        .await;
+
+        let cage = match claim_result {
+            Ok(cage) => {
+                session.commit_transaction().await.map_err(internal_error)?;
+                cage
+            }
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully claimed device"),
+            ClaimedDeviceDto {
+                serial_number: device_claim.serial_number,
+                cage_id: cage.cage_id,
+                claimed_at: now.to_rfc3339(),
+            },
+            None,
+        ))
+    }
+
+    /// Holds a bootstrapping device until its claim code is claimed, then
+    /// hands over its token. The token is only ever handed over once.
+    pub async fn provision_device(
+        &self,
+        provision_device: ProvisionDeviceDto,
+    ) -> Result<ApiSuccessResponse<ProvisionDeviceResponse>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let provisioning_repo = ProvisioningRepository::new(&db);
+        let spm_repo = SpmRepository::new(&db);
+
+        let claim_code_hash =
+            hash_id_with_secret(&normalize_claim_code(&provision_device.claim_code));
+        let device_claim = match provisioning_repo
+            .find_device_claim_by_code_hash(&claim_code_hash)
+            .await?
+        {
+            Some(device_claim) if device_claim.serial_number == provision_device.serial_number => {
+                device_claim
+            }
+            _ => return Err(not_found_error((), "Device does not exist")),
+        };
+        if device_claim.status == ClaimStatus::Delivered {
+            return Err(ApiErrorResponse::new(
+                410,
+                String::from("Device has already been provisioned"),
+            ));
+        }
+
+        let deadline = Instant::now() + PROVISION_WAIT;
+        let delivered_claim = loop {
+            if let Some(delivered_claim) = provisioning_repo
+                .mark_device_delivered(device_claim.id)
+                .await?
+            {
+                break delivered_claim;
+            }
+            if Instant::now() + PROVISION_POLL_INTERVAL > deadline {
+                return Ok(ApiSuccessResponse::new(
+                    String::from("Device has not been claimed yet"),
+                    ProvisionDeviceResponse {
+                        status: ProvisionStatus::Pending,
+                        cage_id: None,
+                        device_token: None,
+                    },
+                    None,
+                ));
+            }
+            sleep(PROVISION_POLL_INTERVAL).await;
+        };
+
+        let cage_id = delivered_claim
+            .cage_id
+            .ok_or_else(|| internal_server_error((), "Claimed device has no cage"))?;
+        let device_token = spm_repo
+            .find_device_token_by_id(&cage_id)
+            .await?
+            .and_then(|device_token| unseal_current_device_token(&device_token))
+            .ok_or_else(|| internal_server_error((), "Claimed device has no usable token"))?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully provisioned device"),
+            ProvisionDeviceResponse {
+                status: ProvisionStatus::Provisioned,
+                cage_id: Some(cage_id),
+                device_token: Some(device_token),
+            },
+            None,
+        ))
+    }
+}
@@ -1,19 +1,56 @@
-use std::{io::Cursor, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    str::FromStr,
+    sync::Arc,
+    time::Duration as StdDuration,
+};
 
-use chrono::Utc;
-use csv::WriterBuilder;
-use mongodb::Client;
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use chrono::{Duration, Utc};
+use csv::{ReaderBuilder, Trim, WriterBuilder};
+use mongodb::{Client, Database};
+use strum::IntoEnumIterator;
+use validator::Validate;
 
 use crate::{
+    config,
     dtos::spm_dtos::{
-        AddNewCageDto, CageCsvDto, CageDto, CagePagination, DownloadCageReportDto, UpdateCageDto,
-        UpdateHealthSettingsDto, UserCageDataResponse,
+        AddNewCageDto, BatchReadingResult, BatchUpdateCageDto, BatchUpdateCageResponse,
+        BulkAddCagesDto, BulkAddCagesResponse, CageCsvDto, CageDto, CageImportResult, CageInfoDto,
+        CagePagination, CageSeriesQuery, CageSeriesResponse, DeviceHeartbeatDto, DeviceStatusDto,
+        DeviceTokenVersionDto, DiseaseRiskPoint, DiseaseRiskQuery, DiseaseRiskResponse,
+        DiseaseRiskSummary, DownloadCageReportDto, EditCageDto, ImportCagesResponse,
+        LatestCageReadingDto, ReadingBucketDto, ReadingStatus, ReassignCageDto, RiskTrend,
+        RotateDeviceTokenDto, RotatedDeviceTokenDto, UpdateCageDto, UpdateHealthSettingsDto,
+        UserCageDataResponse,
+    },
+    models::{
+        plan::ExportFormat,
+        provisioning::ProvisioningSheet,
+        spm::{
+            BucketSize, Cage, CageReading, CageWithDeviceToken, DeviceTokenVersion,
+            DiseaseRiskBucket, HealthSettings, SensorMetric, SpmDeviceToken,
+        },
+        user::{User, UserType},
+    },
+    notifiers::Notification,
+    repository::{
+        alert_repository::AlertRepository, provisioning_repository::ProvisioningRepository,
+        spm_repository::SpmRepository, user_repository::UserRepository,
+    },
+    services::{
+        alert_service::AlertService,
+        notification_service::NotificationService,
+        plan_service::{ensure_cage_quota, ensure_export_format},
+        telemetry_service::{CageEvent, TelemetryHub},
     },
-    models::spm::{CageWithDeviceToken, HealthSettings, SpmDeviceToken},
-    repository::{spm_repository::SpmRepository, user_repository::UserRepository},
     utils::{
-        error_handler::{internal_error, internal_server_error},
-        helper::{generate_pdf_for_cage_data, generate_secure_device_token, hash_id_with_secret},
+        error_handler::{http_error, internal_error, internal_server_error, not_found_error},
+        helper::{
+            generate_pdf_for_cage_data, generate_provisioning_sheet_pdf,
+            generate_secure_device_token, seal_device_token, unseal_device_token,
+        },
         response::{
             ApiErrorResponse, ApiSuccessResponse, SpmDownloadCsvSuccessResponse,
             SpmDownloadPdfSuccessResponse,
@@ -21,6 +58,15 @@ use crate::{
     },
 };
 
+const MAX_IMPORT_CAGES: usize = 1000;
+const MAX_SERIES_BUCKETS: i64 = 5000;
+// A cage whose newest reading is older than this is reported as stale.
+const STALE_READING_AFTER: Duration = Duration::minutes(10);
+const OFFLINE_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(30);
+// How much a probability must move between the first and last bucket of a
+// window before it counts as rising or falling rather than steady.
+const RISK_TREND_TOLERANCE: f32 = 0.05;
+
 pub struct SpmService {
     client: Arc<Client>,
 }
@@ -39,20 +85,18 @@ impl SpmService {
         let user_repo = UserRepository::new(&db);
         let spm_repo = SpmRepository::new(&db);
 
-        match user_repo.find_user_by_id(&user_id).await? {
+        let found_user = match user_repo.find_user_by_id(&user_id).await? {
             Some(user) => user,
             None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
         };
+        let assignable_monitors = find_assignable_monitors(&user_repo, &found_user).await?;
+        if !assignable_monitors.contains(&add_new_cage.assigned_monitor) {
+            return Err(not_found_error((), "User does not exist"));
+        }
+        ensure_cage_quota(&db, &found_user, 1).await?;
 
         let cage = add_new_cage.to_model();
-        let (device_token, hashed_device_token) = generate_secure_device_token();
-
-        let spm_device_token = SpmDeviceToken {
-            id: cage.cage_id.clone(),
-            token: hashed_device_token,
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-        };
+        let (device_token, spm_device_token) = issue_device_token(&cage.cage_id);
 
         let mut session = self.client.start_session().await.map_err(internal_error)?;
         session.start_transaction().await.map_err(internal_error)?;
@@ -78,13 +122,6 @@ impl SpmService {
             device_token,
             assigned_monitor: new_cage.assigned_monitor,
             livestock_no: new_cage.livestock_no,
-            temperature: new_cage.temperature,
-            humidity: new_cage.humidity,
-            pressure: new_cage.pressure,
-            ammonia: new_cage.ammonia,
-            co2: new_cage.co2,
-            object_recognition: new_cage.object_recognition,
-            timestamp: new_cage.timestamp.to_rfc3339(),
             created_at: new_cage.created_at.to_rfc3339(),
             updated_at: new_cage.updated_at.to_rfc3339(),
         };
@@ -96,19 +133,318 @@ impl SpmService {
         ))
     }
 
+    pub async fn add_new_cages_in_bulk(
+        &self,
+        user_id: String,
+        bulk_add_cages: BulkAddCagesDto,
+    ) -> Result<ApiSuccessResponse<BulkAddCagesResponse>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let user_repo = UserRepository::new(&db);
+        let spm_repo = SpmRepository::new(&db);
+        let provisioning_repo = ProvisioningRepository::new(&db);
+
+        let found_user = match user_repo.find_user_by_id(&user_id).await? {
+            Some(user) => user,
+            None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
+        };
+        let assignable_monitors = find_assignable_monitors(&user_repo, &found_user).await?;
+        if bulk_add_cages
+            .cages
+            .iter()
+            .any(|add_new_cage| !assignable_monitors.contains(&add_new_cage.assigned_monitor))
+        {
+            return Err(not_found_error((), "User does not exist"));
+        }
+        ensure_cage_quota(&db, &found_user, bulk_add_cages.cages.len()).await?;
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let bulk_result = async {
+            let mut cages = Vec::with_capacity(bulk_add_cages.cages.len());
+            for add_new_cage in bulk_add_cages.cages {
+                let cage = add_new_cage.to_model();
+                let (device_token, spm_device_token) = issue_device_token(&cage.cage_id);
+                let new_cage = spm_repo
+                    .create_new_cage(&mut session, cage, spm_device_token)
+                    .await?;
+                cages.push(CageWithDeviceToken {
+                    id: new_cage.id.to_string(),
+                    cage_id: new_cage.cage_id,
+                    device_token,
+                    assigned_monitor: new_cage.assigned_monitor,
+                    livestock_no: new_cage.livestock_no,
+                    created_at: new_cage.created_at.to_rfc3339(),
+                    updated_at: new_cage.updated_at.to_rfc3339(),
+                });
+            }
+
+            let provisioning_sheet = ProvisioningSheet {
+                id: ObjectId::new(),
+                created_by: user_id.clone(),
+                cage_ids: cages.iter().map(|cage| cage.cage_id.clone()).collect(),
+                created_at: Utc::now(),
+            };
+            provisioning_repo
+                .create_provisioning_sheet(&mut session, &provisioning_sheet)
+                .await?;
+
+            Ok(BulkAddCagesResponse {
+                cages,
+                provisioning_sheet_id: provisioning_sheet.id.to_string(),
+            })
+        }
+        .await;
+
+        let bulk_add_cages_response = match bulk_result {
+            Ok(response) => {
+                session.commit_transaction().await.map_err(internal_error)?;
+                response
+            }
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("New cages added succesfully"),
+            bulk_add_cages_response,
+            None,
+        ))
+    }
+
+    /// Creates every cage in a CSV of `cage_id,livestock_no,assigned_monitor`
+    /// rows. Nothing is created unless every row is valid.
+    pub async fn import_cages_from_csv(
+        &self,
+        user_id: String,
+        csv_data: String,
+    ) -> Result<ApiSuccessResponse<ImportCagesResponse>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let user_repo = UserRepository::new(&db);
+        let spm_repo = SpmRepository::new(&db);
+
+        let found_user = match user_repo.find_user_by_id(&user_id).await? {
+            Some(user) => user,
+            None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
+        };
+
+        let mut reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(csv_data.as_bytes());
+        let headers = reader
+            .headers()
+            .map_err(|err| ApiErrorResponse::new(400, format!("Invalid CSV: {err}")))?
+            .clone();
+
+        let mut results = Vec::new();
+        let mut valid_cages = Vec::new();
+        let mut seen_cage_ids = HashSet::new();
+        for record in reader.records() {
+            let (line, parsed) = match record {
+                Ok(record) => (
+                    record.position().map_or(0, |position| position.line()),
+                    record
+                        .deserialize::<AddNewCageDto>(Some(&headers))
+                        .map_err(|err| err.to_string()),
+                ),
+                Err(err) => (
+                    err.position().map_or(0, |position| position.line()),
+                    Err(err.to_string()),
+                ),
+            };
+            let checked = parsed.and_then(|add_new_cage| {
+                add_new_cage
+                    .validate()
+                    .map_err(|err| err.to_string().replace('\n', ", "))?;
+                if !seen_cage_ids.insert(add_new_cage.cage_id.clone()) {
+                    return Err(String::from("cageID appears more than once in the file"));
+                }
+                Ok(add_new_cage)
+            });
+
+            match checked {
+                Ok(add_new_cage) => {
+                    results.push(CageImportResult {
+                        line,
+                        cage_id: Some(add_new_cage.cage_id.clone()),
+                        created: false,
+                        reason: None,
+                        device_token: None,
+                    });
+                    valid_cages.push((results.len() - 1, add_new_cage));
+                }
+                Err(reason) => results.push(CageImportResult {
+                    line,
+                    cage_id: None,
+                    created: false,
+                    reason: Some(reason),
+                    device_token: None,
+                }),
+            }
+        }
+
+        if results.is_empty() || results.len() > MAX_IMPORT_CAGES {
+            return Err(ApiErrorResponse::new(
+                400,
+                format!("An import must contain between 1 and {MAX_IMPORT_CAGES} cages"),
+            ));
+        }
+
+        let cage_ids: Vec<String> = valid_cages
+            .iter()
+            .map(|(_, add_new_cage)| add_new_cage.cage_id.clone())
+            .collect();
+        let existing_cage_ids: HashSet<String> = spm_repo
+            .find_cages_by_cage_ids(&cage_ids)
+            .await?
+            .into_iter()
+            .map(|cage| cage.cage_id)
+            .collect();
+        let assignable_monitors = find_assignable_monitors(&user_repo, &found_user).await?;
+        for (index, add_new_cage) in &valid_cages {
+            if existing_cage_ids.contains(&add_new_cage.cage_id) {
+                results[*index].reason = Some(String::from("Cage already exist"));
+            } else if !assignable_monitors.contains(&add_new_cage.assigned_monitor) {
+                results[*index].reason = Some(String::from("assigned_monitor does not exist"));
+            }
+        }
+
+        let rejected = results
+            .iter()
+            .filter(|result| result.reason.is_some())
+            .count();
+        if rejected > 0 {
+            return Ok(ApiSuccessResponse::new(
+                String::from("No cages were imported, fix the rejected rows and try again"),
+                ImportCagesResponse {
+                    created: 0,
+                    rejected,
+                    results,
+                },
+                None,
+            ));
+        }
+
+        ensure_cage_quota(&db, &found_user, valid_cages.len()).await?;
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let import_result = async {
+            let mut device_tokens = Vec::with_capacity(valid_cages.len());
+            for (index, add_new_cage) in valid_cages {
+                let cage = add_new_cage.to_model();
+                let (device_token, spm_device_token) = issue_device_token(&cage.cage_id);
+                spm_repo
+                    .create_new_cage(&mut session, cage, spm_device_token)
+                    .await?;
+                device_tokens.push((index, device_token));
+            }
+            Ok(device_tokens)
+        }
+        .await;
+
+        let device_tokens = match import_result {
+            Ok(device_tokens) => {
+                session.commit_transaction().await.map_err(internal_error)?;
+                device_tokens
+            }
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        };
+
+        let created = device_tokens.len();
+        for (index, device_token) in device_tokens {
+            results[index].created = true;
+            results[index].device_token = Some(device_token);
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully imported cages"),
+            ImportCagesResponse {
+                created,
+                rejected: 0,
+                results,
+            },
+            None,
+        ))
+    }
+
+    /// Renders the provisioning sheet for cages created in bulk. A sheet can
+    /// only be downloaded once; concurrent downloads race to consume it and
+    /// only the winner gets the PDF.
+    pub async fn download_provisioning_sheet(
+        &self,
+        user_id: String,
+        sheet_id: String,
+    ) -> Result<SpmDownloadPdfSuccessResponse, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let provisioning_repo = ProvisioningRepository::new(&db);
+
+        let sheet_id = ObjectId::parse_str(&sheet_id)
+            .map_err(|_| not_found_error((), "Provisioning sheet does not exist"))?;
+        let provisioning_sheet = provisioning_repo
+            .find_provisioning_sheet(sheet_id, &user_id)
+            .await?
+            .ok_or_else(|| not_found_error((), "Provisioning sheet does not exist"))?;
+
+        let mut cages = Vec::with_capacity(provisioning_sheet.cage_ids.len());
+        for cage_id in &provisioning_sheet.cage_ids {
+            // Cages deleted or rotated since the sheet was created are left out.
+            let Some(cage) = spm_repo.find_cage_by_cage_id(cage_id).await? else {
+                continue;
+            };
+            let Some(device_token) = spm_repo
+                .find_device_token_by_id(cage_id)
+                .await?
+                .and_then(|device_token| unseal_current_device_token(&device_token))
+            else {
+                continue;
+            };
+            cages.push(CageWithDeviceToken {
+                id: cage.id.to_string(),
+                cage_id: cage.cage_id,
+                device_token,
+                assigned_monitor: cage.assigned_monitor,
+                livestock_no: cage.livestock_no,
+                created_at: cage.created_at.to_rfc3339(),
+                updated_at: cage.updated_at.to_rfc3339(),
+            });
+        }
+
+        let pdf_data = generate_provisioning_sheet_pdf(cages, &config::device::api_base_url())
+            .map_err(internal_error)?;
+
+        // Only consumed once rendered, so a failure leaves the sheet to retry.
+        if !provisioning_repo
+            .consume_provisioning_sheet(sheet_id, &user_id)
+            .await?
+        {
+            return Err(not_found_error((), "Provisioning sheet does not exist"));
+        }
+        Ok(SpmDownloadPdfSuccessResponse::new(pdf_data).with_filename("provisioning_sheet.pdf"))
+    }
+
     pub async fn fetch_all_users_cage_data(
         &self,
-        assigned_monitor: String,
+        user_id: String,
         cage_pagination: CagePagination,
     ) -> Result<ApiSuccessResponse<UserCageDataResponse>, ApiErrorResponse> {
         let db = self.client.database("fiyadb");
         let spm_repo = SpmRepository::new(&db);
 
         let (offset, limit) = (cage_pagination.offset, cage_pagination.limit);
-        let (cages, total_cage_data) = spm_repo
-            .find_all_users_cage_data_with_pagination(assigned_monitor, offset, limit)
+        let cages = find_accessible_cages(&db, &user_id).await?;
+        let cage_ids: Vec<String> = cages.iter().map(|cage| cage.cage_id.clone()).collect();
+        let (readings, total_cage_data) = spm_repo
+            .find_cage_readings_with_pagination(&cage_ids, offset, limit)
             .await?;
-        let cage_dtos = cages.into_iter().map(CageDto::from).collect();
+        let cage_dtos = join_cage_readings(&cages, readings);
         let user_cage_data = UserCageDataResponse {
             total_cage_data,
             cages: cage_dtos,
@@ -121,49 +457,607 @@ impl SpmService {
         ))
     }
 
+    pub async fn fetch_latest_users_cage_readings(
+        &self,
+        user_id: String,
+    ) -> Result<ApiSuccessResponse<Vec<LatestCageReadingDto>>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let cages = find_accessible_cages(&db, &user_id).await?;
+        let cage_ids: Vec<String> = cages.iter().map(|cage| cage.cage_id.clone()).collect();
+        let mut readings_by_cage: HashMap<String, CageReading> = spm_repo
+            .find_latest_cage_readings(&cage_ids)
+            .await?
+            .into_iter()
+            .map(|reading| (reading.cage_id.clone(), reading))
+            .collect();
+
+        let now = Utc::now();
+        let latest_readings = cages
+            .iter()
+            .map(|cage| {
+                let reading = readings_by_cage.remove(&cage.cage_id);
+                let age = reading.as_ref().map(|reading| now - reading.timestamp);
+                let status = match age {
+                    Some(age) if age <= STALE_READING_AFTER => ReadingStatus::Online,
+                    Some(_) => ReadingStatus::Stale,
+                    None => ReadingStatus::NoData,
+                };
+
+                LatestCageReadingDto {
+                    cage_id: cage.cage_id.clone(),
+                    assigned_monitor: cage.assigned_monitor.clone(),
+                    livestock_no: cage.livestock_no,
+                    status,
+                    age_secs: age.map(|age| age.num_seconds()),
+                    reading: reading.map(|reading| CageDto::from_reading(cage, reading)),
+                }
+            })
+            .collect();
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched latest cage readings"),
+            latest_readings,
+            None,
+        ))
+    }
+
     pub async fn update_cage_info(
         &self,
-        cage_id: String,
+        found_cage: Cage,
         update_cage_dto: UpdateCageDto,
-        device_token: String,
+        telemetry_hub: &TelemetryHub,
     ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
         let db = self.client.database("fiyadb");
         let spm_repo = SpmRepository::new(&db);
 
-        let found_spm_device_token = match spm_repo.find_device_token_by_id(&cage_id).await? {
-            Some(spm_device_token) => spm_device_token,
-            None => return Err(ApiErrorResponse::new(403, String::from("Unauthorized"))),
+        update_cage_dto
+            .check_reading()
+            .map_err(|reason| ApiErrorResponse::new(400, reason))?;
+        let reading = update_cage_dto.to_model(found_cage.cage_id.clone());
+        let new_reading = spm_repo.add_cage_reading(reading).await?;
+        self.mark_device_seen(&found_cage, None, telemetry_hub)
+            .await;
+        self.evaluate_alerts(
+            &found_cage,
+            std::slice::from_ref(&new_reading),
+            telemetry_hub,
+        )
+        .await;
+        telemetry_hub.publish(CageEvent::Reading(CageDto::from_reading(
+            &found_cage,
+            new_reading,
+        )));
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully updated cage info"),
+            (),
+            None,
+        ))
+    }
+
+    pub async fn batch_update_cage_info(
+        &self,
+        found_cage: Cage,
+        batch_update_cage: BatchUpdateCageDto,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<ApiSuccessResponse<BatchUpdateCageResponse>, ApiErrorResponse> {
+        let update_cage_dtos = batch_update_cage.readings;
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        self.mark_device_seen(&found_cage, None, telemetry_hub)
+            .await;
+
+        let mut results = Vec::with_capacity(update_cage_dtos.len());
+        let mut readings = Vec::new();
+        let mut reading_indexes = Vec::new();
+
+        for (index, update_cage_dto) in update_cage_dtos.into_iter().enumerate() {
+            match update_cage_dto.check_reading() {
+                Ok(()) => {
+                    readings.push(update_cage_dto.to_model(found_cage.cage_id.clone()));
+                    reading_indexes.push(index);
+                }
+                Err(reason) => results.push(BatchReadingResult {
+                    index,
+                    accepted: false,
+                    reason: Some(reason),
+                }),
+            }
+        }
+
+        if !readings.is_empty() {
+            let failed: HashMap<usize, String> = spm_repo
+                .add_many_cage_readings(&readings)
+                .await?
+                .into_iter()
+                .collect();
+
+            let mut accepted_readings = Vec::with_capacity(readings.len());
+            for (position, reading) in readings.into_iter().enumerate() {
+                let index = reading_indexes[position];
+                match failed.get(&position) {
+                    Some(reason) => results.push(BatchReadingResult {
+                        index,
+                        accepted: false,
+                        reason: Some(reason.clone()),
+                    }),
+                    None => {
+                        results.push(BatchReadingResult {
+                            index,
+                            accepted: true,
+                            reason: None,
+                        });
+                        accepted_readings.push(reading);
+                    }
+                }
+            }
+
+            accepted_readings.sort_by_key(|reading| reading.timestamp);
+            self.evaluate_alerts(&found_cage, &accepted_readings, telemetry_hub)
+                .await;
+            for reading in accepted_readings {
+                telemetry_hub.publish(CageEvent::Reading(CageDto::from_reading(
+                    &found_cage,
+                    reading,
+                )));
+            }
+        }
+
+        results.sort_by_key(|result| result.index);
+        let accepted = results.iter().filter(|result| result.accepted).count();
+        let batch_response = BatchUpdateCageResponse {
+            accepted,
+            rejected: results.len() - accepted,
+            results,
         };
 
-        let hashed_device_token = hash_id_with_secret(&device_token);
-        if hashed_device_token != found_spm_device_token.token {
-            return Err(ApiErrorResponse::new(403, String::from("Unauthorized")));
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully processed cage readings"),
+            batch_response,
+            None,
+        ))
+    }
+
+    pub async fn record_device_heartbeat(
+        &self,
+        found_cage: Cage,
+        device_heartbeat_dto: DeviceHeartbeatDto,
+        telemetry_hub: &TelemetryHub,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        self.mark_device_seen(&found_cage, Some(&device_heartbeat_dto), telemetry_hub)
+            .await;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully recorded heartbeat"),
+            (),
+            None,
+        ))
+    }
+
+    pub async fn edit_users_cage(
+        &self,
+        found_cage: Cage,
+        edit_cage: EditCageDto,
+    ) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let cage = spm_repo
+            .update_cage(
+                &found_cage.cage_id,
+                doc! { "livestock_no": edit_cage.livestock_no },
+            )
+            .await?
+            .ok_or_else(|| not_found_error((), "Cage does not exist"))?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully updated cage"),
+            CageInfoDto::from(cage),
+            None,
+        ))
+    }
+
+    /// Hands a cage to another user in the caller's account: the caller
+    /// themselves, a customer they created or staff they added.
+    pub async fn reassign_users_cage(
+        &self,
+        user_id: String,
+        found_cage: Cage,
+        reassign_cage: ReassignCageDto,
+    ) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let user_repo = UserRepository::new(&db);
+
+        let found_user = user_repo
+            .find_user_by_id(&user_id)
+            .await?
+            .ok_or_else(|| ApiErrorResponse::new(401, String::from("Unauthorized")))?;
+        let new_monitor = user_repo
+            .find_user_by_id(&reassign_cage.assigned_monitor)
+            .await?
+            .filter(|new_monitor| can_assign_cage_to(&found_user, new_monitor))
+            .ok_or_else(|| not_found_error((), "User does not exist"))?;
+        let cage = spm_repo
+            .update_cage(
+                &found_cage.cage_id,
+                doc! { "assigned_monitor": new_monitor.id.to_string() },
+            )
+            .await?
+            .ok_or_else(|| not_found_error((), "Cage does not exist"))?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully reassigned cage"),
+            CageInfoDto::from(cage),
+            None,
+        ))
+    }
+
+    /// Archives or restores a cage. An archived cage keeps its history, but
+    /// its device can no longer authenticate.
+    pub async fn set_users_cage_archived(
+        &self,
+        found_cage: Cage,
+        archived: bool,
+    ) -> Result<ApiSuccessResponse<CageInfoDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        if found_cage.archived_at.is_some() == archived {
+            let message = if archived {
+                "Cage is already archived"
+            } else {
+                "Cage is not archived"
+            };
+            return Err(ApiErrorResponse::new(409, String::from(message)));
         }
 
-        let found_cage = match spm_repo.find_cage_by_cage_id(&cage_id).await? {
-            Some(cage) => cage,
-            None => {
-                return Err(ApiErrorResponse::new(
-                    401,
-                    String::from("cage does not exits"),
-                ))
+        let archived_at = archived.then(BsonDateTime::now);
+        let cage = spm_repo
+            .update_cage(&found_cage.cage_id, doc! { "archived_at": archived_at })
+            .await?
+            .ok_or_else(|| not_found_error((), "Cage does not exist"))?;
+
+        let message = if archived {
+            "Succesfully archived cage"
+        } else {
+            "Succesfully restored cage"
+        };
+        Ok(ApiSuccessResponse::new(
+            String::from(message),
+            CageInfoDto::from(cage),
+            None,
+        ))
+    }
+
+    /// Permanently deletes a cage with its readings, alerts, device token and
+    /// health settings, releasing its device claim and dropping it from
+    /// pending provisioning sheets.
+    pub async fn delete_users_cage(
+        &self,
+        found_cage: Cage,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let alert_repo = AlertRepository::new(&db);
+        let provisioning_repo = ProvisioningRepository::new(&db);
+        let cage_id = found_cage.cage_id;
+
+        // Readings can't be deleted inside the transaction. Deleting them
+        // first means a failure leaves the cage in place to retry.
+        spm_repo.delete_cage_readings(&cage_id).await?;
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let delete_result = async {
+            alert_repo
+                .delete_cage_alerts(&mut session, &cage_id)
+                .await?;
+            provisioning_repo
+                .release_cage_device_claims(&mut session, &cage_id)
+                .await?;
+            provisioning_repo
+                .remove_cage_from_provisioning_sheets(&mut session, &cage_id)
+                .await?;
+            spm_repo.delete_cage(&mut session, &cage_id).await
+        }
+        .await;
+
+        match delete_result {
+            Ok(()) => session.commit_transaction().await.map_err(internal_error)?,
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        }
+
+        // The device could still report until its token was deleted above.
+        if let Err(err) = spm_repo.delete_cage_readings(&cage_id).await {
+            tracing::error!(
+                "failed to delete late readings of cage {}: {:?}",
+                cage_id,
+                err
+            );
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully deleted cage"),
+            (),
+            None,
+        ))
+    }
+
+    pub async fn get_users_cage_device(
+        &self,
+        cage: Cage,
+    ) -> Result<ApiSuccessResponse<DeviceStatusDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let device = spm_repo
+            .find_device_by_cage_id(&cage.cage_id)
+            .await?
+            .ok_or_else(|| not_found_error((), "Device has not reported yet"))?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched device status"),
+            DeviceStatusDto::from_device(&cage, device),
+            None,
+        ))
+    }
+
+    /// Periodically flips devices that have gone silent for `offline_after` to
+    /// offline, publishing a device event and notifying the cage's monitor.
+    pub async fn run_offline_monitor(self, telemetry_hub: TelemetryHub, offline_after: Duration) {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let notification_service = NotificationService::new(self.client.clone());
+
+        loop {
+            tokio::time::sleep(OFFLINE_CHECK_INTERVAL).await;
+
+            loop {
+                let device = match spm_repo
+                    .mark_next_silent_device_offline(Utc::now() - offline_after)
+                    .await
+                {
+                    Ok(Some(device)) => device,
+                    Ok(None) => break,
+                    Err(err) => {
+                        tracing::error!("failed to check for offline devices: {:?}", err);
+                        break;
+                    }
+                };
+
+                let cage = match spm_repo.find_cage_by_cage_id(&device.id).await {
+                    Ok(Some(cage)) => cage,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::error!("failed to find cage {}: {:?}", device.id, err);
+                        continue;
+                    }
+                };
+
+                let notification = Notification {
+                    event: String::from("device.offline"),
+                    subject: format!("Device on cage {} is offline", cage.cage_id),
+                    body: format!(
+                        "The device on cage {} has not reported since {}.",
+                        cage.cage_id,
+                        device.last_seen_at.to_rfc3339()
+                    ),
+                    sensitive: false,
+                };
+                if let Err(err) = notification_service
+                    .notify_user(&cage.assigned_monitor, notification)
+                    .await
+                {
+                    tracing::error!("failed to queue device offline notification: {:?}", err);
+                }
+                telemetry_hub.publish(CageEvent::Device(DeviceStatusDto::from_device(
+                    &cage, device,
+                )));
+            }
+        }
+    }
+
+    async fn mark_device_seen(
+        &self,
+        cage: &Cage,
+        device_heartbeat_dto: Option<&DeviceHeartbeatDto>,
+        telemetry_hub: &TelemetryHub,
+    ) {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let firmware_version =
+            device_heartbeat_dto.map(|heartbeat| heartbeat.firmware_version.as_str());
+        let uptime_secs = device_heartbeat_dto.and_then(|heartbeat| heartbeat.uptime_secs);
+        let previous_device = match spm_repo
+            .record_device_seen(&cage.cage_id, firmware_version, uptime_secs)
+            .await
+        {
+            Ok(previous_device) => previous_device,
+            Err(err) => {
+                tracing::error!("failed to record device for {}: {:?}", cage.cage_id, err);
+                return;
             }
         };
 
-        let update_cage = update_cage_dto.to_model(
-            found_cage.cage_id,
-            found_cage.livestock_no,
-            found_cage.assigned_monitor,
+        // Only a device coming back from offline is news to dashboards.
+        if previous_device.is_some_and(|device| !device.online)
+            && let Ok(Some(device)) = spm_repo.find_device_by_cage_id(&cage.cage_id).await
+        {
+            telemetry_hub.publish(CageEvent::Device(DeviceStatusDto::from_device(
+                cage, device,
+            )));
+        }
+    }
+
+    pub async fn rotate_device_token(
+        &self,
+        found_cage: Cage,
+        rotate_device_token_dto: RotateDeviceTokenDto,
+    ) -> Result<ApiSuccessResponse<RotatedDeviceTokenDto>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let cage_id = found_cage.cage_id;
+        let mut spm_device_token = find_device_token(&spm_repo, &cage_id).await?;
+        let expected_version = spm_device_token.version;
+        let now = Utc::now();
+
+        // A revoked token never gets a grace period, and rotating again cuts
+        // short any grace period left over from an earlier rotation.
+        let grace_until = (rotate_device_token_dto.grace_period_secs > 0
+            && spm_device_token.revoked_at.is_none())
+        .then(|| now + Duration::seconds(rotate_device_token_dto.grace_period_secs as i64));
+        for version in spm_device_token
+            .versions
+            .iter_mut()
+            .filter(|version| version.revoked_at.is_none())
+        {
+            if version.version == expected_version && grace_until.is_some() {
+                version.expires_at = grace_until;
+            } else if version.expires_at.is_none_or(|expires_at| expires_at > now) {
+                version.revoked_at = Some(now);
+            }
+        }
+
+        let (device_token, hashed_device_token) = generate_secure_device_token();
+        let version = expected_version + 1;
+        let sealed_device_token = seal_device_token(
+            &device_token,
+            &SpmDeviceToken::seal_context(&cage_id, version),
         );
-        let _ = spm_repo.add_cage_new_info(update_cage).await?;
+        spm_device_token.versions.push(DeviceTokenVersion {
+            version,
+            token: hashed_device_token.clone(),
+            sealed_token: Some(sealed_device_token),
+            created_at: now,
+            expires_at: None,
+            revoked_at: None,
+        });
+        spm_device_token.token = hashed_device_token;
+        spm_device_token.version = version;
+        spm_device_token.revoked_at = None;
+        spm_device_token.updated_at = now;
+
+        if !spm_repo
+            .replace_device_token(&spm_device_token, expected_version)
+            .await?
+        {
+            return Err(ApiErrorResponse::new(
+                409,
+                String::from("Device token was changed by another request, try again"),
+            ));
+        }
+
+        let rotated_device_token = RotatedDeviceTokenDto {
+            cage_id,
+            device_token,
+            version,
+            previous_token_valid_until: grace_until.map(|at| at.to_rfc3339()),
+        };
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully rotated device token"),
+            rotated_device_token,
+            None,
+        ))
+    }
+
+    pub async fn revoke_device_token(
+        &self,
+        found_cage: Cage,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let mut spm_device_token = find_device_token(&spm_repo, &found_cage.cage_id).await?;
+        if spm_device_token.revoked_at.is_some() {
+            return Err(ApiErrorResponse::new(
+                409,
+                String::from("Device token is already revoked"),
+            ));
+        }
+
+        let now = Utc::now();
+        for version in spm_device_token
+            .versions
+            .iter_mut()
+            .filter(|version| version.revoked_at.is_none())
+        {
+            version.revoked_at = Some(now);
+        }
+        spm_device_token.revoked_at = Some(now);
+        spm_device_token.updated_at = now;
+
+        let expected_version = spm_device_token.version;
+        if !spm_repo
+            .replace_device_token(&spm_device_token, expected_version)
+            .await?
+        {
+            return Err(ApiErrorResponse::new(
+                409,
+                String::from("Device token was changed by another request, try again"),
+            ));
+        }
 
         Ok(ApiSuccessResponse::new(
-            String::from("Succesfully updated cage info"),
+            String::from("Succesfully revoked device token"),
             (),
             None,
         ))
     }
 
+    pub async fn fetch_device_token_history(
+        &self,
+        found_cage: Cage,
+    ) -> Result<ApiSuccessResponse<Vec<DeviceTokenVersionDto>>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let spm_device_token = find_device_token(&spm_repo, &found_cage.cage_id).await?;
+        let current_version = spm_device_token
+            .revoked_at
+            .is_none()
+            .then_some(spm_device_token.version);
+        let mut versions = spm_device_token.versions;
+        versions.sort_by_key(|version| std::cmp::Reverse(version.version));
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched device token history"),
+            versions
+                .into_iter()
+                .map(|version| DeviceTokenVersionDto::from_version(version, current_version))
+                .collect(),
+            None,
+        ))
+    }
+
+    async fn evaluate_alerts(
+        &self,
+        cage: &Cage,
+        readings: &[CageReading],
+        telemetry_hub: &TelemetryHub,
+    ) {
+        // The readings are already stored, so a failure here must not make the
+        // device retry and duplicate them.
+        let alert_service = AlertService::new(self.client.clone());
+        for reading in readings {
+            if let Err(err) = alert_service
+                .evaluate_reading(cage, reading, telemetry_hub)
+                .await
+            {
+                tracing::error!("failed to evaluate alerts for {}: {:?}", cage.cage_id, err);
+            }
+        }
+    }
+
     pub async fn generate_cage_report_in_csv_format(
         &self,
         id: String,
@@ -173,20 +1067,23 @@ impl SpmService {
         let user_repo = UserRepository::new(&db);
         let spm_repo = SpmRepository::new(&db);
 
-        match user_repo.find_user_by_id(&id).await? {
+        let found_user = match user_repo.find_user_by_id(&id).await? {
             Some(user) => user,
             None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
         };
+        ensure_export_format(&db, &found_user, ExportFormat::Csv).await?;
 
-        let cages = spm_repo
-            .find_cage_data_by_date_range(&payload.cage_id, payload.start_date, payload.end_date)
+        let cage = find_accessible_cage(&db, &id, &payload.cage_id).await?;
+        let readings = spm_repo
+            .find_cage_readings_by_date_range(&cage.cage_id, payload.start_date, payload.end_date)
             .await?;
+        let cages = join_cage_readings(std::slice::from_ref(&cage), readings);
 
         let mut wrt = WriterBuilder::new().from_writer(Cursor::new(Vec::new()));
 
         cages
-            .iter()
-            .try_for_each(|cage| wrt.serialize(CageCsvDto::from(cage.clone())))
+            .into_iter()
+            .try_for_each(|cage| wrt.serialize(CageCsvDto::from(cage)))
             .map_err(internal_error)?;
 
         let cage_csv = wrt
@@ -206,14 +1103,17 @@ impl SpmService {
         let user_repo = UserRepository::new(&db);
         let spm_repo = SpmRepository::new(&db);
 
-        match user_repo.find_user_by_id(&id).await? {
+        let found_user = match user_repo.find_user_by_id(&id).await? {
             Some(user) => user,
             None => return Err(ApiErrorResponse::new(401, String::from("Unauthorized"))),
         };
+        ensure_export_format(&db, &found_user, ExportFormat::Pdf).await?;
 
-        let cages = spm_repo
-            .find_cage_data_by_date_range(&payload.cage_id, payload.start_date, payload.end_date)
+        let cage = find_accessible_cage(&db, &id, &payload.cage_id).await?;
+        let readings = spm_repo
+            .find_cage_readings_by_date_range(&cage.cage_id, payload.start_date, payload.end_date)
             .await?;
+        let cages = join_cage_readings(std::slice::from_ref(&cage), readings);
 
         let pdf_data = generate_pdf_for_cage_data(cages).map_err(internal_error)?;
         Ok(SpmDownloadPdfSuccessResponse::new(pdf_data))
@@ -231,10 +1131,12 @@ impl SpmService {
             Some(user) => user,
             None => return Err(ApiErrorResponse::new(403, String::from("Unauthorized"))),
         };
+        ensure_export_format(&db, &found_user, ExportFormat::Csv).await?;
 
-        let cages = spm_repo
-            .find_all_users_cage_data(found_user.id.to_string())
-            .await?;
+        let cages = find_accessible_cages(&db, &found_user.id.to_string()).await?;
+        let cage_ids: Vec<String> = cages.iter().map(|cage| cage.cage_id.clone()).collect();
+        let readings = spm_repo.find_cage_readings(&cage_ids).await?;
+        let cages = join_cage_readings(&cages, readings);
         let mut wrt = WriterBuilder::new().from_writer(Cursor::new(Vec::new()));
 
         for cage in cages {
@@ -262,29 +1164,125 @@ impl SpmService {
             Some(user) => user,
             None => return Err(ApiErrorResponse::new(403, String::from("Unauthorized"))),
         };
+        ensure_export_format(&db, &found_user, ExportFormat::Pdf).await?;
 
-        let cages = spm_repo
-            .find_all_users_cage_data(found_user.id.to_string())
-            .await?;
+        let cages = find_accessible_cages(&db, &found_user.id.to_string()).await?;
+        let cage_ids: Vec<String> = cages.iter().map(|cage| cage.cage_id.clone()).collect();
+        let readings = spm_repo.find_cage_readings(&cage_ids).await?;
+        let cages = join_cage_readings(&cages, readings);
         let pdf_data = generate_pdf_for_cage_data(cages).map_err(internal_error)?;
         Ok(SpmDownloadPdfSuccessResponse::new(pdf_data))
     }
 
-    pub async fn get_cage_health_settings_by_cage_id(
+    pub async fn fetch_cage_series(
         &self,
-        cage_id: String,
-    ) -> Result<ApiSuccessResponse<HealthSettings>, ApiErrorResponse> {
+        found_cage: Cage,
+        cage_series_query: CageSeriesQuery,
+    ) -> Result<ApiSuccessResponse<CageSeriesResponse>, ApiErrorResponse> {
         let db = self.client.database("fiyadb");
         let spm_repo = SpmRepository::new(&db);
 
-        if (spm_repo.find_cage_by_cage_id(&cage_id).await?).is_none() {
+        let bucket_size = BucketSize::from_str(&cage_series_query.bucket)
+            .map_err(|e| http_error(e, 400, "Invalid bucket size, use e.g. 5m, 1h or 1d"))?;
+        let metrics: Vec<SensorMetric> = match &cage_series_query.metrics {
+            Some(metrics) => metrics
+                .split(',')
+                .map(|metric| SensorMetric::from_str(metric.trim()))
+                .collect::<Result<_, _>>()
+                .map_err(|e| http_error(e, 400, "Invalid metric"))?,
+            None => SensorMetric::iter().collect(),
+        };
+
+        let (start_date, end_date) = (cage_series_query.start_date, cage_series_query.end_date);
+        if start_date >= end_date {
+            return Err(ApiErrorResponse::new(
+                400,
+                String::from("start date must be before end date"),
+            ));
+        }
+        let bucket_count =
+            (end_date - start_date).num_seconds() / bucket_size.duration().num_seconds();
+        if bucket_count > MAX_SERIES_BUCKETS {
             return Err(ApiErrorResponse::new(
-                401,
-                String::from("Cage does not exit"),
+                400,
+                format!(
+                    "Too many buckets, use a larger bucket size to stay under {MAX_SERIES_BUCKETS}"
+                ),
             ));
         }
 
-        let health_settings = match spm_repo.find_health_settings_by_cage_id(&cage_id).await? {
+        let cage_id = found_cage.cage_id;
+        let buckets = spm_repo
+            .aggregate_cage_readings(&cage_id, start_date, end_date, bucket_size, &metrics)
+            .await?;
+        let cage_series = CageSeriesResponse {
+            cage_id,
+            start_date: start_date.to_rfc3339(),
+            end_date: end_date.to_rfc3339(),
+            bucket: bucket_size.to_string(),
+            metrics: metrics.iter().map(|metric| metric.to_string()).collect(),
+            buckets: buckets.into_iter().map(ReadingBucketDto::from).collect(),
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched cage series"),
+            cage_series,
+            None,
+        ))
+    }
+
+    pub async fn fetch_cage_disease_risk(
+        &self,
+        found_cage: Cage,
+        disease_risk_query: DiseaseRiskQuery,
+    ) -> Result<ApiSuccessResponse<DiseaseRiskResponse>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let cage_id = found_cage.cage_id;
+
+        let window_end = Utc::now();
+        let window_start = window_end - Duration::hours(disease_risk_query.window_hours as i64);
+        let buckets = spm_repo
+            .aggregate_disease_risk(
+                &cage_id,
+                window_start,
+                window_end,
+                disease_risk_query.bucket_minutes,
+            )
+            .await?;
+
+        let summaries = vec![
+            summarize_disease_risk("coccidiosis", &buckets, |bucket| bucket.coccidiosis),
+            summarize_disease_risk("newcastle", &buckets, |bucket| bucket.newcastle),
+            summarize_disease_risk("salmonella", &buckets, |bucket| bucket.salmonella),
+            summarize_disease_risk("healthy", &buckets, |bucket| bucket.healthy),
+        ];
+        let disease_risk = DiseaseRiskResponse {
+            cage_id,
+            window_start: window_start.to_rfc3339(),
+            window_end: window_end.to_rfc3339(),
+            summaries,
+            points: buckets.into_iter().map(DiseaseRiskPoint::from).collect(),
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched disease risk"),
+            disease_risk,
+            None,
+        ))
+    }
+
+    pub async fn get_cage_health_settings_by_cage_id(
+        &self,
+        found_cage: Cage,
+    ) -> Result<ApiSuccessResponse<HealthSettings>, ApiErrorResponse> {
+        let db = self.client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+
+        let health_settings = match spm_repo
+            .find_health_settings_by_cage_id(&found_cage.cage_id)
+            .await?
+        {
             Some(health_settings) => health_settings,
             None => {
                 return Err(ApiErrorResponse::new(
@@ -303,20 +1301,13 @@ impl SpmService {
 
     pub async fn update_cage_health_settings(
         &self,
-        cage_id: String,
+        found_cage: Cage,
         update_health_settings_dto: UpdateHealthSettingsDto,
     ) -> Result<ApiSuccessResponse<HealthSettings>, ApiErrorResponse> {
         let db = self.client.database("fiyadb");
         let spm_repo = SpmRepository::new(&db);
 
-        if (spm_repo.find_cage_by_cage_id(&cage_id).await?).is_none() {
-            return Err(ApiErrorResponse::new(
-                403,
-                String::from("Cage does not exist"),
-            ));
-        }
-
-        let health_settings = update_health_settings_dto.to_model(cage_id);
+        let health_settings = update_health_settings_dto.to_model(found_cage.cage_id);
         let updated_health_settings = spm_repo.update_health_settings(health_settings).await?;
         Ok(ApiSuccessResponse::new(
             String::from("Successfully updated health settings"),
@@ -325,3 +1316,631 @@ impl SpmService {
         ))
     }
 }
+
+fn join_cage_readings(cages: &[Cage], readings: Vec<CageReading>) -> Vec<CageDto> {
+    let cages_by_id: HashMap<&str, &Cage> = cages
+        .iter()
+        .map(|cage| (cage.cage_id.as_str(), cage))
+        .collect();
+
+    readings
+        .into_iter()
+        .filter_map(|reading| {
+            cages_by_id
+                .get(reading.cage_id.as_str())
+                .map(|cage| CageDto::from_reading(cage, reading))
+        })
+        .collect()
+}
+
+fn summarize_disease_risk(
+    disease: &str,
+    buckets: &[DiseaseRiskBucket],
+    probability: impl Fn(&DiseaseRiskBucket) -> f32,
+) -> DiseaseRiskSummary {
+    let total_readings: u32 = buckets.iter().map(|bucket| bucket.readings).sum();
+    let weighted_sum: f32 = buckets
+        .iter()
+        .map(|bucket| probability(bucket) * bucket.readings as f32)
+        .sum();
+    let average = if total_readings > 0 {
+        weighted_sum / total_readings as f32
+    } else {
+        0.0
+    };
+    let first = buckets.first().map(&probability).unwrap_or_default();
+    let latest = buckets.last().map(&probability).unwrap_or_default();
+    let peak = buckets.iter().map(&probability).fold(0.0, f32::max);
+
+    let trend = if latest - first > RISK_TREND_TOLERANCE {
+        RiskTrend::Rising
+    } else if first - latest > RISK_TREND_TOLERANCE {
+        RiskTrend::Falling
+    } else {
+        RiskTrend::Steady
+    };
+
+    DiseaseRiskSummary {
+        disease: disease.to_string(),
+        latest,
+        average,
+        peak,
+        trend,
+    }
+}
+
+/// Finds a cage the user may manage: their own cage, the cage linked to a
+/// customer through `spm_id`, any cage of a user an admin or owner created
+/// (directly or through the staff of a customer), or for farm staff, any cage
+/// of the user who added them.
+/// Cages outside the user's reach are reported as missing so their existence
+/// isn't leaked.
+pub async fn find_accessible_cage(
+    db: &Database,
+    user_id: &str,
+    cage_id: &str,
+) -> Result<Cage, ApiErrorResponse> {
+    let spm_repo = SpmRepository::new(db);
+    let user_repo = UserRepository::new(db);
+    let not_found = || not_found_error((), "Cage does not exist");
+
+    let cage = spm_repo
+        .find_cage_by_cage_id(cage_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if cage.assigned_monitor == user_id {
+        return Ok(cage);
+    }
+
+    let user = user_repo
+        .find_user_by_id(user_id)
+        .await?
+        .ok_or_else(not_found)?;
+    let managed_users = find_managed_users(&user_repo, &user).await?;
+    if can_access_cage(&user, &managed_users, &cage) {
+        Ok(cage)
+    } else {
+        Err(not_found())
+    }
+}
+
+/// Every cage the user may manage, by the same rules as
+/// `find_accessible_cage`, newest first.
+pub async fn find_accessible_cages(
+    db: &Database,
+    user_id: &str,
+) -> Result<Vec<Cage>, ApiErrorResponse> {
+    let spm_repo = SpmRepository::new(db);
+    let user_repo = UserRepository::new(db);
+
+    let Some(user) = user_repo.find_user_by_id(user_id).await? else {
+        return Ok(Vec::new());
+    };
+    let managed_users = find_managed_users(&user_repo, &user).await?;
+    let account_users = || std::iter::once(&user).chain(&managed_users);
+    let assigned_monitors: Vec<String> = account_users()
+        .map(|account_user| account_user.id)
+        .chain(user.created_by)
+        .map(|id| id.to_string())
+        .collect();
+    let linked_cage_ids: Vec<String> = account_users()
+        .filter_map(|account_user| account_user.spm_id.clone())
+        .collect();
+
+    let cages = spm_repo
+        .find_cages_by_assigned_monitors(&assigned_monitors, &linked_cage_ids)
+        .await?;
+    Ok(cages
+        .into_iter()
+        .filter(|cage| can_access_cage(&user, &managed_users, cage))
+        .collect())
+}
+
+/// The users an admin or owner created, and the staff those users added in
+/// turn. Anyone else manages no one.
+async fn find_managed_users(
+    user_repo: &UserRepository,
+    user: &User,
+) -> Result<Vec<User>, ApiErrorResponse> {
+    match UserType::from_str(&user.r#type) {
+        Ok(UserType::SuperAdmin | UserType::Admin | UserType::Owner) => {
+            user_repo.find_users_created_under(user.id).await
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The IDs of the users `user` may assign a new cage to, by the rules of
+/// `can_assign_cage_to`.
+async fn find_assignable_monitors(
+    user_repo: &UserRepository,
+    user: &User,
+) -> Result<HashSet<String>, ApiErrorResponse> {
+    let managed_users = user_repo.find_users_managed_by(user).await?;
+    Ok(assignable_monitors(user, &managed_users))
+}
+
+fn assignable_monitors(user: &User, candidates: &[User]) -> HashSet<String> {
+    std::iter::once(user)
+        .chain(candidates)
+        .filter(|candidate| can_assign_cage_to(user, candidate))
+        .map(|candidate| candidate.id.to_string())
+        .collect()
+}
+
+/// Whether `user` may hand one of their cages to `new_monitor`. Users
+/// outside the caller's account are treated as missing.
+fn can_assign_cage_to(user: &User, new_monitor: &User) -> bool {
+    let is_created_customer = user
+        .created_customers
+        .as_ref()
+        .is_some_and(|created_customers| created_customers.contains(&new_monitor.id));
+
+    new_monitor.id == user.id || is_created_customer || new_monitor.created_by == Some(user.id)
+}
+
+/// Whether `user` may manage `cage`. `managed_users` are the users below an
+/// admin or owner; entries that don't lead back to `user` through
+/// `created_by` are ignored.
+fn can_access_cage(user: &User, managed_users: &[User], cage: &Cage) -> bool {
+    let monitors_cage = |user: &User| {
+        user.id.to_string() == cage.assigned_monitor
+            || user.spm_id.as_deref() == Some(cage.cage_id.as_str())
+    };
+
+    match UserType::from_str(&user.r#type) {
+        Ok(UserType::SuperAdmin | UserType::Admin | UserType::Owner) => {
+            monitors_cage(user)
+                || users_below(user, managed_users)
+                    .into_iter()
+                    .any(monitors_cage)
+        }
+        Ok(UserType::Customer) => monitors_cage(user),
+        Ok(UserType::FarmManager | UserType::Worker | UserType::Veterinarian) => {
+            monitors_cage(user)
+                || user
+                    .created_by
+                    .is_some_and(|created_by| created_by.to_string() == cage.assigned_monitor)
+        }
+        Err(_) => false,
+    }
+}
+
+/// The users in `candidates` whose `created_by` chain leads back to
+/// `manager`.
+fn users_below<'a>(manager: &User, candidates: &'a [User]) -> Vec<&'a User> {
+    let mut managers = HashSet::from([manager.id]);
+    let mut below = Vec::new();
+    loop {
+        let found = candidates.iter().filter(|candidate| {
+            !managers.contains(&candidate.id)
+                && candidate
+                    .created_by
+                    .is_some_and(|created_by| managers.contains(&created_by))
+        });
+        let found: Vec<&User> = found.collect();
+        if found.is_empty() {
+            return below;
+        }
+        managers.extend(found.iter().map(|user| user.id));
+        below.extend(found);
+    }
+}
+
+async fn find_device_token(
+    spm_repo: &SpmRepository,
+    cage_id: &str,
+) -> Result<SpmDeviceToken, ApiErrorResponse> {
+    spm_repo
+        .find_device_token_by_id(cage_id)
+        .await?
+        .ok_or_else(|| not_found_error((), "Device token does not exist"))
+}
+
+/// Issues a fresh device token for a new cage, returning the plaintext token
+/// alongside the record to store.
+pub fn issue_device_token(cage_id: &str) -> (String, SpmDeviceToken) {
+    let (device_token, hashed_device_token) = generate_secure_device_token();
+    let sealed_device_token =
+        seal_device_token(&device_token, &SpmDeviceToken::seal_context(cage_id, 1));
+    let spm_device_token = SpmDeviceToken::new(
+        cage_id.to_string(),
+        hashed_device_token,
+        sealed_device_token,
+    );
+
+    (device_token, spm_device_token)
+}
+
+/// Recovers the plaintext of a cage's current token, if it was sealed and is
+/// still usable.
+pub fn unseal_current_device_token(device_token: &SpmDeviceToken) -> Option<String> {
+    if device_token.revoked_at.is_some() {
+        return None;
+    }
+    let context = SpmDeviceToken::seal_context(&device_token.id, device_token.version);
+    device_token
+        .versions
+        .iter()
+        .find(|version| version.version == device_token.version && version.revoked_at.is_none())
+        .and_then(|version| version.sealed_token.as_deref())
+        .and_then(|sealed_token| unseal_device_token(sealed_token, &context))
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::Document;
+
+    use super::*;
+    use crate::{
+        config::database::test_mongodb_connection,
+        migrations::run_migrations,
+        models::{
+            provisioning::{ClaimStatus, DeviceClaim, ProvisioningSheet},
+            spm::ObjectRecognition,
+        },
+        utils::helper::tests::set_test_spm_secret,
+    };
+
+    fn user(user_type: UserType, created_by: Option<&User>) -> User {
+        User {
+            id: ObjectId::new(),
+            name: String::from("Test User"),
+            email: format!("{}@example.com", ObjectId::new()),
+            phone_number: String::from("+2348012345678"),
+            password: String::new(),
+            r#type: user_type.to_string(),
+            created_customers: None,
+            created_by: created_by.map(|creator| creator.id),
+            spm_id: None,
+            disabled: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn cages_can_only_be_assigned_within_the_account() {
+        let mut admin = user(UserType::Admin, None);
+        let customer = user(UserType::Customer, Some(&admin));
+        admin.created_customers = Some(vec![customer.id]);
+        let staff = user(UserType::Worker, Some(&customer));
+        let foreign_admin = user(UserType::Admin, None);
+        let foreign_customer = user(UserType::Customer, Some(&foreign_admin));
+
+        assert!(can_assign_cage_to(&admin, &admin));
+        assert!(can_assign_cage_to(&admin, &customer));
+        assert!(can_assign_cage_to(&customer, &staff));
+        assert!(!can_assign_cage_to(&admin, &foreign_admin));
+        assert!(!can_assign_cage_to(&admin, &foreign_customer));
+        assert!(!can_assign_cage_to(&customer, &admin));
+        assert!(!can_assign_cage_to(&staff, &customer));
+    }
+
+    #[test]
+    fn new_cages_cannot_go_to_foreign_monitors() {
+        let mut admin = user(UserType::Admin, None);
+        let customer = user(UserType::Customer, Some(&admin));
+        admin.created_customers = Some(vec![customer.id]);
+        let worker = user(UserType::Worker, Some(&admin));
+        let foreign_admin = user(UserType::Admin, None);
+        let foreign_customer = user(UserType::Customer, Some(&foreign_admin));
+
+        let candidates = [customer.clone(), worker.clone(), foreign_customer.clone()];
+        let monitors = assignable_monitors(&admin, &candidates);
+        assert!(monitors.contains(&admin.id.to_string()));
+        assert!(monitors.contains(&customer.id.to_string()));
+        assert!(monitors.contains(&worker.id.to_string()));
+        assert!(!monitors.contains(&foreign_customer.id.to_string()));
+        assert!(!monitors.contains(&foreign_admin.id.to_string()));
+
+        // Workers may only create cages for themselves.
+        let monitors = assignable_monitors(&worker, &candidates);
+        assert_eq!(monitors, HashSet::from([worker.id.to_string()]));
+    }
+
+    fn cage(assigned_monitor: &User) -> Cage {
+        Cage {
+            id: ObjectId::new(),
+            cage_id: format!("cage-{}", ObjectId::new()),
+            assigned_monitor: assigned_monitor.id.to_string(),
+            livestock_no: 10,
+            archived_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn customers_only_access_their_own_cages() {
+        let admin = user(UserType::Admin, None);
+        let customer = user(UserType::Customer, Some(&admin));
+        let other_customer = user(UserType::Customer, Some(&admin));
+
+        assert!(can_access_cage(&customer, &[], &cage(&customer)));
+        assert!(!can_access_cage(&customer, &[], &cage(&other_customer)));
+        assert!(!can_access_cage(&customer, &[], &cage(&admin)));
+    }
+
+    #[test]
+    fn admins_only_access_their_own_customers_cages() {
+        let mut admin = user(UserType::Admin, None);
+        let customer = user(UserType::Customer, Some(&admin));
+        admin.created_customers = Some(vec![customer.id]);
+        let foreign_admin = user(UserType::Admin, None);
+        let foreign_customer = user(UserType::Customer, Some(&foreign_admin));
+
+        assert!(can_access_cage(&admin, &[], &cage(&admin)));
+        let customer_cage = cage(&customer);
+        assert!(can_access_cage(&admin, &[customer], &customer_cage));
+        assert!(!can_access_cage(&admin, &[], &cage(&foreign_admin)));
+        // Even if a foreign customer is passed in, it isn't the admin's.
+        let foreign_cage = cage(&foreign_customer);
+        assert!(!can_access_cage(&admin, &[foreign_customer], &foreign_cage));
+    }
+
+    #[test]
+    fn spm_id_links_a_cage_to_its_customer() {
+        let mut admin = user(UserType::Admin, None);
+        let mut customer = user(UserType::Customer, Some(&admin));
+        admin.created_customers = Some(vec![customer.id]);
+        let linked_cage = cage(&admin);
+        customer.spm_id = Some(linked_cage.cage_id.clone());
+        let other_customer = user(UserType::Customer, Some(&admin));
+
+        assert!(can_access_cage(&customer, &[], &linked_cage));
+        assert!(!can_access_cage(&other_customer, &[], &linked_cage));
+        assert!(can_access_cage(&admin, &[customer], &linked_cage));
+    }
+
+    #[test]
+    fn staff_access_the_cages_of_whoever_added_them() {
+        let admin = user(UserType::Admin, None);
+        let customer = user(UserType::Customer, Some(&admin));
+        let other_customer = user(UserType::Customer, Some(&admin));
+
+        for staff_type in [
+            UserType::FarmManager,
+            UserType::Worker,
+            UserType::Veterinarian,
+        ] {
+            let staff = user(staff_type, Some(&customer));
+            assert!(can_access_cage(&staff, &[], &cage(&customer)));
+            assert!(can_access_cage(&staff, &[], &cage(&staff)));
+            assert!(!can_access_cage(&staff, &[], &cage(&other_customer)));
+        }
+    }
+
+    #[test]
+    fn managers_keep_access_to_cages_handed_to_their_staff() {
+        let admin = user(UserType::Admin, None);
+        let owner = user(UserType::Owner, Some(&admin));
+        let worker = user(UserType::Worker, Some(&owner));
+        let admins_worker = user(UserType::Worker, Some(&admin));
+        let foreign_owner = user(UserType::Owner, None);
+        let foreign_worker = user(UserType::Worker, Some(&foreign_owner));
+
+        assert!(can_assign_cage_to(&owner, &worker));
+        let workers_cage = cage(&worker);
+        let account = [owner.clone(), worker];
+        let owners_staff = &account[1..];
+        assert!(can_access_cage(&owner, owners_staff, &workers_cage));
+        assert!(can_access_cage(&admin, &account, &workers_cage));
+        // Without the owner in between, the worker isn't the admin's.
+        assert!(!can_access_cage(&admin, owners_staff, &workers_cage));
+
+        assert!(can_assign_cage_to(&admin, &admins_worker));
+        let admins_workers_cage = cage(&admins_worker);
+        assert!(can_access_cage(
+            &admin,
+            &[admins_worker],
+            &admins_workers_cage
+        ));
+
+        let foreign_cage = cage(&foreign_worker);
+        assert!(!can_access_cage(&owner, &[foreign_worker], &foreign_cage));
+    }
+
+    #[test]
+    fn unknown_roles_access_nothing() {
+        let mut stranger = user(UserType::Customer, None);
+        stranger.r#type = String::from("intruder");
+
+        assert!(!can_access_cage(&stranger, &[], &cage(&stranger)));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB server at TEST_DATABASE_URL"]
+    async fn lists_every_accessible_cage() {
+        let client = test_mongodb_connection().await;
+        let db = client.database("fiyadb");
+
+        let admin = user(UserType::Admin, None);
+        let owner = user(UserType::Owner, Some(&admin));
+        let worker = user(UserType::Worker, Some(&owner));
+        let foreign_admin = user(UserType::Admin, None);
+        db.collection::<User>("users")
+            .insert_many([&admin, &owner, &worker, &foreign_admin])
+            .await
+            .unwrap();
+        let owners_cage = cage(&owner);
+        let workers_cage = cage(&worker);
+        let foreign_cage = cage(&foreign_admin);
+        db.collection::<Cage>("cages")
+            .insert_many([&owners_cage, &workers_cage, &foreign_cage])
+            .await
+            .unwrap();
+
+        let accessible_cage_ids = |user: &User| {
+            let db = db.clone();
+            let user_id = user.id.to_string();
+            async move {
+                let mut cage_ids: Vec<String> = find_accessible_cages(&db, &user_id)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|cage| cage.cage_id)
+                    .collect();
+                cage_ids.sort();
+                cage_ids
+            }
+        };
+        let sorted = |mut cage_ids: Vec<String>| {
+            cage_ids.sort();
+            cage_ids
+        };
+
+        let account_cage_ids = sorted(vec![
+            owners_cage.cage_id.clone(),
+            workers_cage.cage_id.clone(),
+        ]);
+        assert_eq!(accessible_cage_ids(&admin).await, account_cage_ids);
+        assert_eq!(accessible_cage_ids(&owner).await, account_cage_ids);
+        assert_eq!(accessible_cage_ids(&worker).await, account_cage_ids);
+        assert_eq!(
+            accessible_cage_ids(&foreign_admin).await,
+            vec![foreign_cage.cage_id]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB replica set at TEST_DATABASE_URL"]
+    async fn deleting_a_cage_cascades() {
+        set_test_spm_secret();
+        let client = test_mongodb_connection().await;
+        run_migrations(&client).await.unwrap();
+        let db = client.database("fiyadb");
+        let spm_repo = SpmRepository::new(&db);
+        let raw_collection = |name: &str| db.collection::<Document>(name);
+
+        let cage_id = format!("test-{}", ObjectId::new());
+        let other_cage_id = format!("test-{}", ObjectId::new());
+        let now = Utc::now();
+        let cage = Cage {
+            id: ObjectId::new(),
+            cage_id: cage_id.clone(),
+            assigned_monitor: ObjectId::new().to_string(),
+            livestock_no: 10,
+            archived_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let (_, spm_device_token) = issue_device_token(&cage_id);
+        let mut session = client.start_session().await.unwrap();
+        spm_repo
+            .create_new_cage(&mut session, cage.clone(), spm_device_token)
+            .await
+            .unwrap();
+        spm_repo
+            .add_cage_reading(CageReading {
+                id: ObjectId::new(),
+                cage_id: cage_id.clone(),
+                temperature: 30.0,
+                humidity: 60.0,
+                pressure: 1000.0,
+                ammonia: 5.0,
+                co2: 800.0,
+                object_recognition: ObjectRecognition {
+                    coccidiosis: 0.0,
+                    newcastle: 0.0,
+                    salmonella: 0.0,
+                    healthy: 1.0,
+                },
+                timestamp: now,
+                created_at: now,
+            })
+            .await
+            .unwrap();
+        for name in ["alerts", "sensor_breaches", "health_settings"] {
+            raw_collection(name)
+                .insert_one(doc! { "cage_id": &cage_id })
+                .await
+                .unwrap();
+        }
+        raw_collection("devices")
+            .insert_one(doc! { "_id": &cage_id })
+            .await
+            .unwrap();
+
+        let device_claim = DeviceClaim {
+            id: ObjectId::new(),
+            serial_number: format!("SN-{}", ObjectId::new()),
+            claim_code_hash: ObjectId::new().to_string(),
+            status: ClaimStatus::Delivered,
+            registered_by: ObjectId::new().to_string(),
+            cage_id: Some(cage_id.clone()),
+            claimed_by: Some(cage.assigned_monitor.clone()),
+            claimed_at: Some(now),
+            delivered_at: Some(now),
+            created_at: now,
+            updated_at: now,
+        };
+        db.collection::<DeviceClaim>("device_claims")
+            .insert_one(&device_claim)
+            .await
+            .unwrap();
+        let shared_sheet = ProvisioningSheet {
+            id: ObjectId::new(),
+            created_by: cage.assigned_monitor.clone(),
+            cage_ids: vec![cage_id.clone(), other_cage_id.clone()],
+            created_at: now,
+        };
+        let single_sheet = ProvisioningSheet {
+            id: ObjectId::new(),
+            cage_ids: vec![cage_id.clone()],
+            ..shared_sheet.clone()
+        };
+        db.collection::<ProvisioningSheet>("provisioning_sheets")
+            .insert_many([&shared_sheet, &single_sheet])
+            .await
+            .unwrap();
+
+        SpmService::new(Arc::new(client.clone()))
+            .delete_users_cage(cage)
+            .await
+            .unwrap();
+
+        for name in [
+            "cages",
+            "cage_readings",
+            "alerts",
+            "sensor_breaches",
+            "health_settings",
+        ] {
+            let remaining = raw_collection(name)
+                .count_documents(doc! { "cage_id": &cage_id })
+                .await
+                .unwrap();
+            assert_eq!(remaining, 0, "{name} still references the cage");
+        }
+        for name in ["device_token", "devices"] {
+            let remaining = raw_collection(name)
+                .count_documents(doc! { "_id": &cage_id })
+                .await
+                .unwrap();
+            assert_eq!(remaining, 0, "{name} still references the cage");
+        }
+
+        let device_claim = db
+            .collection::<DeviceClaim>("device_claims")
+            .find_one_and_delete(doc! { "_id": device_claim.id })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(device_claim.status, ClaimStatus::Unclaimed);
+        assert!(device_claim.cage_id.is_none() && device_claim.claimed_by.is_none());
+
+        let sheets = db.collection::<ProvisioningSheet>("provisioning_sheets");
+        let shared_sheet = sheets
+            .find_one_and_delete(doc! { "_id": shared_sheet.id })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shared_sheet.cage_ids, vec![other_cage_id]);
+        let single_sheet = sheets
+            .find_one(doc! { "_id": single_sheet.id })
+            .await
+            .unwrap();
+        assert!(single_sheet.is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::extract::ws::{Message, WebSocket};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::broadcast, time::timeout};
+
+use crate::{
+    dtos::{
+        alert_dtos::AlertDto,
+        spm_dtos::{CageDto, DeviceStatusDto},
+    },
+    services::spm_service::find_accessible_cage,
+};
+
+const TELEMETRY_CHANNEL_CAPACITY: usize = 1024;
+const SOCKET_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum CageEvent {
+    Reading(CageDto),
+    Alert(AlertDto),
+    Device(DeviceStatusDto),
+}
+
+impl CageEvent {
+    fn cage_id(&self) -> &str {
+        match self {
+            CageEvent::Reading(cage) => &cage.cage_id,
+            CageEvent::Alert(alert) => &alert.cage_id,
+            CageEvent::Device(device) => &device.cage_id,
+        }
+    }
+
+    fn assigned_monitor(&self) -> &str {
+        match self {
+            CageEvent::Reading(cage) => &cage.assigned_monitor,
+            CageEvent::Alert(alert) => &alert.assigned_monitor,
+            CageEvent::Device(device) => &device.assigned_monitor,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TelemetryHub {
+    sender: broadcast::Sender<CageEvent>,
+}
+
+impl TelemetryHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(TELEMETRY_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: CageEvent) {
+        // An error here only means nobody is currently listening.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CageEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for TelemetryHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { cage_id: String },
+    Unsubscribe { cage_id: String },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed { cage_id: String },
+    Unsubscribed { cage_id: String },
+    Lagged { skipped: u64 },
+    Error { message: &'static str },
+}
+
+pub struct TelemetryService {
+    client: Arc<Client>,
+    hub: TelemetryHub,
+}
+
+impl TelemetryService {
+    pub fn new(client: Arc<Client>, hub: TelemetryHub) -> Self {
+        Self { client, hub }
+    }
+
+    pub async fn handle_socket(self, mut socket: WebSocket, user_id: String) {
+        let mut events = self.hub.subscribe();
+        // Subscribed cage IDs, with the monitor each cage had when access was
+        // last checked.
+        let mut subscriptions: HashMap<String, String> = HashMap::new();
+
+        loop {
+            tokio::select! {
+                incoming = socket.recv() => {
+                    let text = match incoming {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => continue,
+                    };
+
+                    let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Subscribe { cage_id }) => {
+                            self.subscribe(&user_id, cage_id, &mut subscriptions).await
+                        }
+                        Ok(ClientMessage::Unsubscribe { cage_id }) => {
+                            subscriptions.remove(&cage_id);
+                            ServerMessage::Unsubscribed { cage_id }
+                        }
+                        Err(_) => ServerMessage::Error { message: "Invalid message" },
+                    };
+
+                    if !send_json(&mut socket, &reply).await {
+                        break;
+                    }
+                }
+                event = events.recv() => {
+                    let delivered = match event {
+                        Ok(event) => {
+                            let Some(assigned_monitor) = subscriptions.get(event.cage_id())
+                            else {
+                                continue;
+                            };
+                            // A reassigned cage may have moved out of reach.
+                            if event.assigned_monitor() != assigned_monitor
+                                && !self
+                                    .recheck_access(&user_id, event.cage_id(), &mut subscriptions)
+                                    .await
+                            {
+                                continue;
+                            }
+                            send_json(&mut socket, &event).await
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            send_json(&mut socket, &ServerMessage::Lagged { skipped }).await
+                        }
+                        Err(broadcast::error::RecvError::Closed) => false,
+                    };
+
+                    if !delivered {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        user_id: &str,
+        cage_id: String,
+        subscriptions: &mut HashMap<String, String>,
+    ) -> ServerMessage {
+        let db = self.client.database("fiyadb");
+
+        match find_accessible_cage(&db, user_id, &cage_id).await {
+            Ok(cage) => {
+                subscriptions.insert(cage_id.clone(), cage.assigned_monitor);
+                ServerMessage::Subscribed { cage_id }
+            }
+            Err(err) if err.status() == 404 => ServerMessage::Error {
+                message: "Cage does not exist",
+            },
+            Err(_) => ServerMessage::Error {
+                message: "Unable to subscribe to cage",
+            },
+        }
+    }
+
+    /// Refreshes a subscription after its cage was reassigned, dropping it
+    /// if the user can no longer access the cage.
+    async fn recheck_access(
+        &self,
+        user_id: &str,
+        cage_id: &str,
+        subscriptions: &mut HashMap<String, String>,
+    ) -> bool {
+        let db = self.client.database("fiyadb");
+
+        match find_accessible_cage(&db, user_id, cage_id).await {
+            Ok(cage) => {
+                subscriptions.insert(cage.cage_id, cage.assigned_monitor);
+                true
+            }
+            Err(_) => {
+                subscriptions.remove(cage_id);
+                false
+            }
+        }
+    }
+}
+
+/// Sends a JSON frame, giving up on clients that stop reading instead of
+/// letting their socket buffer grow without bound.
+async fn send_json<T: Serialize>(socket: &mut WebSocket, payload: &T) -> bool {
+    let text = match serde_json::to_string(payload) {
+        Ok(text) => text,
+        Err(_) => return true,
+    };
+
+    matches!(
+        timeout(SOCKET_SEND_TIMEOUT, socket.send(Message::Text(text))).await,
+        Ok(Ok(()))
+    )
+}
@@ -1,13 +1,31 @@
+use bson::{doc, oid::ObjectId};
 use mongodb::Client;
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
+use subtle::ConstantTimeEq;
 
 use crate::{
-    dtos::user::{CreateAdminUserDto, CreateCustomerDto},
-    models::user::NewUser,
-    repository::user_repository::UserRepository,
-    utils::response::{ApiErrorResponse, ApiSuccessResponse},
+    config,
+    dtos::user::{
+        BootstrapSuperAdminDto, CreateAdminUserDto, CreateCustomerDto, CreateStaffDto,
+        UpdateUserDto,
+    },
+    models::user::{NewUser, User, UserType},
+    repository::{
+        invitation_repository::InvitationRepository,
+        spm_repository::SpmRepository,
+        user_repository::{is_write_conflict, UserRepository},
+    },
+    services::{invitation_service::InvitationService, plan_service::find_account_plan},
+    utils::{
+        error_handler::{http_error, internal_error, not_found_error},
+        response::{ApiErrorResponse, ApiSuccessResponse},
+    },
 };
 
+/// How many times a customer creation that lost a write conflict to a
+/// concurrent request for the same admin is retried.
+const MAX_TRANSACTION_ATTEMPTS: usize = 10;
+
 pub struct UserService {
     client: Arc<Client>,
 }
@@ -34,6 +52,62 @@ impl UserService {
         ))
     }
 
+    pub async fn bootstrap_super_admin(
+        &self,
+        payload: BootstrapSuperAdminDto,
+    ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new_async(&database).await?;
+
+        let setup_token = config::setup::setup_token()
+            .ok_or_else(|| not_found_error((), "Setup is not enabled"))?;
+        if !bool::from(payload.setup_token.as_bytes().ct_eq(setup_token.as_bytes())) {
+            return Err(ApiErrorResponse::new(
+                401,
+                String::from("Invalid setup token"),
+            ));
+        }
+        if user_repository.is_super_admin_setup_complete().await? {
+            return Err(ApiErrorResponse::new(
+                409,
+                String::from("Setup has already been completed"),
+            ));
+        }
+
+        let new_user = payload.to_model()?;
+        let super_admin_id = new_user.id;
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let bootstrap_result = async {
+            user_repository
+                .complete_super_admin_setup(&mut session, super_admin_id)
+                .await?;
+            user_repository
+                .create_user_in_session(&mut session, new_user)
+                .await
+        }
+        .await;
+
+        let user = match bootstrap_result {
+            Ok(user) => {
+                session.commit_transaction().await.map_err(internal_error)?;
+                user
+            }
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        };
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully created super admin"),
+            user,
+            None,
+        ))
+    }
+
     pub async fn create_customer_user(
         &self,
         admin_id: String,
@@ -41,32 +115,493 @@ impl UserService {
     ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
         let database = self.client.database("fiyadb");
         let user_repository = UserRepository::new_async(&database).await?;
-        let admin_user = user_repository.find_admin_user_by_id(admin_id).await?;
-
-        match admin_user {
-            Some(admin_user) => {
-                if let Some(created_customers) = admin_user.created_customers {
-                    if created_customers.len() > 4 {
-                        return Err(ApiErrorResponse::new(
-                            401,
-                            String::from("Maximum number of customers has been created"),
-                        ));
+        let admin_user = match user_repository.find_admin_user_by_id(admin_id).await? {
+            Some(admin_user) => admin_user,
+            None => {
+                return Err(ApiErrorResponse::new(
+                    403,
+                    String::from("Only admins can create customers"),
+                ))
+            }
+        };
+
+        let role = match payload.role.as_deref() {
+            Some(role) => grantable_role(&admin_user, role, false)?,
+            None => UserType::Customer,
+        };
+        let plan = find_account_plan(&database, &admin_user).await?;
+        let new_user = payload.to_model(admin_user.id, role)?;
+        let customer_id = new_user.id;
+
+        let invitation_service = InvitationService::new(self.client.clone());
+
+        let mut attempt = 1;
+        let (user, invitation, token) = loop {
+            let mut session = self.client.start_session().await.map_err(internal_error)?;
+            session.start_transaction().await.map_err(internal_error)?;
+
+            // Claiming a slot on the admin, inserting the customer and inviting
+            // them commit together, so concurrent requests can't overshoot the
+            // limit and no customer is left without an invitation.
+            let create_result = async {
+                let has_slot = user_repository
+                    .push_created_customer(
+                        &mut session,
+                        admin_user.id,
+                        customer_id,
+                        plan.max_customers as usize,
+                    )
+                    .await?;
+                if !has_slot {
+                    return Err(ApiErrorResponse::new(
+                        409,
+                        String::from("Maximum number of customers has been created"),
+                    ));
+                }
+                let user = user_repository
+                    .create_user_in_session(&mut session, new_user.clone())
+                    .await?;
+                let (invitation, token) = invitation_service
+                    .invite_user(&mut session, &admin_user.id.to_string(), &user)
+                    .await?;
+                Ok((user, invitation, token))
+            }
+            .await;
+
+            match create_result {
+                Ok(created) => {
+                    session.commit_transaction().await.map_err(internal_error)?;
+                    break created;
+                }
+                Err(err) => {
+                    session.abort_transaction().await.map_err(internal_error)?;
+                    // Another request for this admin won the write; try again
+                    // against the slots it left.
+                    if attempt < MAX_TRANSACTION_ATTEMPTS && is_write_conflict(&err) {
+                        attempt += 1;
+                        continue;
                     }
+                    return Err(err);
                 }
+            }
+        };
 
-                let new_user = payload.to_model(admin_user.id)?;
-                let user = user_repository.create_user(new_user).await?;
+        invitation_service
+            .notify_invited_user(&user, &invitation, &token)
+            .await;
 
-                Ok(ApiSuccessResponse::new(
-                    String::from("Succesfully created a user"),
-                    user,
-                    None,
-                ))
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully created a user"),
+            user,
+            None,
+        ))
+    }
+
+    pub async fn create_staff_user(
+        &self,
+        manager_id: String,
+        payload: CreateStaffDto,
+    ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new_async(&database).await?;
+        let manager = find_manager(&user_repository, &manager_id).await?;
+
+        let role = grantable_role(&manager, &payload.role, true)?;
+        let new_user = payload.to_model(manager.id, role)?;
+        let invitation_service = InvitationService::new(self.client.clone());
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let create_result = async {
+            let user = user_repository
+                .create_user_in_session(&mut session, new_user)
+                .await?;
+            let (invitation, token) = invitation_service
+                .invite_user(&mut session, &manager.id.to_string(), &user)
+                .await?;
+            Ok((user, invitation, token))
+        }
+        .await;
+
+        let (user, invitation, token) = match create_result {
+            Ok(created) => {
+                session.commit_transaction().await.map_err(internal_error)?;
+                created
             }
-            None => Err(ApiErrorResponse::new(
-                401,
-                String::from("Unauthorized user doesn't exist"),
-            )),
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        };
+
+        invitation_service
+            .notify_invited_user(&user, &invitation, &token)
+            .await;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully created a user"),
+            user,
+            None,
+        ))
+    }
+
+    pub async fn fetch_managed_users(
+        &self,
+        manager_id: String,
+    ) -> Result<ApiSuccessResponse<Vec<NewUser>>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new(&database);
+        let manager = find_manager(&user_repository, &manager_id).await?;
+
+        let users = user_repository
+            .find_users_managed_by(&manager)
+            .await?
+            .into_iter()
+            .map(NewUser::from)
+            .collect();
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched users"),
+            users,
+            None,
+        ))
+    }
+
+    pub async fn fetch_managed_user(
+        &self,
+        manager_id: String,
+        user_id: String,
+    ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new(&database);
+        let manager = find_manager(&user_repository, &manager_id).await?;
+        let user = find_managed_user(&user_repository, &manager, &user_id).await?;
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully fetched user"),
+            NewUser::from(user),
+            None,
+        ))
+    }
+
+    pub async fn update_managed_user(
+        &self,
+        manager_id: String,
+        user_id: String,
+        payload: UpdateUserDto,
+    ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new(&database);
+        let invitation_repository = InvitationRepository::new(&database);
+        let manager = find_manager(&user_repository, &manager_id).await?;
+        let user = find_managed_user(&user_repository, &manager, &user_id).await?;
+
+        if let Some(role) = &payload.role {
+            let is_staff = UserType::from_str(&user.r#type).is_ok_and(|role| role.is_staff());
+            grantable_role(&manager, role, is_staff)?;
+        }
+        let update = payload.to_document();
+        if update.is_empty() {
+            return Err(ApiErrorResponse::new(
+                400,
+                String::from("No changes were provided"),
+            ));
+        }
+
+        let user = user_repository
+            .update_user(user.id, update)
+            .await?
+            .ok_or_else(|| not_found_error((), "User does not exist"))?;
+        if let Some(email) = &payload.email {
+            invitation_repository
+                .update_open_invitation_email(&user.id.to_string(), email)
+                .await?;
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully updated user"),
+            NewUser::from(user),
+            None,
+        ))
+    }
+
+    pub async fn set_managed_user_disabled(
+        &self,
+        manager_id: String,
+        user_id: String,
+        disabled: bool,
+    ) -> Result<ApiSuccessResponse<NewUser>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new(&database);
+        let manager = find_manager(&user_repository, &manager_id).await?;
+        let user = find_managed_user(&user_repository, &manager, &user_id).await?;
+
+        let user = user_repository
+            .update_user(user.id, doc! { "disabled": disabled })
+            .await?
+            .ok_or_else(|| not_found_error((), "User does not exist"))?;
+        if disabled {
+            // Sign them out everywhere; access tokens are refused by the auth
+            // middleware from here on.
+            user_repository
+                .delete_user_refresh_token(user.id.to_string())
+                .await?;
         }
+
+        let message = if disabled {
+            "Succesfully disabled user"
+        } else {
+            "Succesfully enabled user"
+        };
+        Ok(ApiSuccessResponse::new(
+            String::from(message),
+            NewUser::from(user),
+            None,
+        ))
+    }
+
+    pub async fn delete_managed_user(
+        &self,
+        manager_id: String,
+        user_id: String,
+    ) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
+        let database = self.client.database("fiyadb");
+        let user_repository = UserRepository::new(&database);
+        let invitation_repository = InvitationRepository::new(&database);
+        let spm_repository = SpmRepository::new(&database);
+        let manager = find_manager(&user_repository, &manager_id).await?;
+        let user = find_managed_user(&user_repository, &manager, &user_id).await?;
+
+        // Their cages would otherwise be left without anyone who can reach them.
+        if spm_repository
+            .count_cages_by_assigned_monitors(&[user.id.to_string()])
+            .await?
+            > 0
+        {
+            return Err(ApiErrorResponse::new(
+                409,
+                String::from("Reassign or delete the user's cages first"),
+            ));
+        }
+
+        let mut session = self.client.start_session().await.map_err(internal_error)?;
+        session.start_transaction().await.map_err(internal_error)?;
+
+        let delete_result = async {
+            invitation_repository
+                .delete_user_invitations(&mut session, &user.id.to_string())
+                .await?;
+            user_repository.delete_user(&mut session, user.id).await
+        }
+        .await;
+
+        match delete_result {
+            Ok(()) => session.commit_transaction().await.map_err(internal_error)?,
+            Err(err) => {
+                session.abort_transaction().await.map_err(internal_error)?;
+                return Err(err);
+            }
+        }
+
+        Ok(ApiSuccessResponse::new(
+            String::from("Succesfully deleted user"),
+            (),
+            None,
+        ))
+    }
+}
+
+async fn find_manager(
+    user_repository: &UserRepository,
+    manager_id: &str,
+) -> Result<User, ApiErrorResponse> {
+    user_repository
+        .find_user_by_id(manager_id)
+        .await?
+        .ok_or_else(|| ApiErrorResponse::new(401, String::from("Unauthorized")))
+}
+
+/// Finds a user `manager` created. Anyone else is reported as missing.
+async fn find_managed_user(
+    user_repository: &UserRepository,
+    manager: &User,
+    user_id: &str,
+) -> Result<User, ApiErrorResponse> {
+    let not_found = || not_found_error((), "User does not exist");
+    let user_id = ObjectId::parse_str(user_id).map_err(|_| not_found())?;
+    let is_created_customer = manager
+        .created_customers
+        .as_ref()
+        .is_some_and(|created_customers| created_customers.contains(&user_id));
+
+    user_repository
+        .find_user_by_id(&user_id.to_string())
+        .await?
+        .filter(|user| user.created_by == Some(manager.id) || is_created_customer)
+        .ok_or_else(not_found)
+}
+
+/// Parses a role `manager` wants to give a user, refusing roles they may not
+/// grant. Staff roles and customer roles aren't interchangeable, since
+/// customers are tracked on their admin and staff aren't.
+fn grantable_role(manager: &User, role: &str, staff: bool) -> Result<UserType, ApiErrorResponse> {
+    let role = UserType::from_str(role).map_err(|e| http_error(e, 400, "Invalid role"))?;
+    let may_grant = UserType::from_str(&manager.r#type)
+        .is_ok_and(|manager_type| manager_type.grantable_roles().contains(&role));
+    if !may_grant {
+        return Err(ApiErrorResponse::new(
+            403,
+            format!("You can't grant the {role} role"),
+        ));
+    }
+    if role.is_staff() != staff {
+        let message = if staff {
+            "Staff can only have a staff role"
+        } else {
+            "Customers can't have a staff role"
+        };
+        return Err(ApiErrorResponse::new(400, String::from(message)));
+    }
+    Ok(role)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use futures::future::join_all;
+
+    use super::*;
+    use crate::{
+        config::database::test_mongodb_connection, migrations::run_migrations, models::plan::Plan,
+        repository::plan_repository::PlanRepository, utils::helper::tests::set_test_spm_secret,
+    };
+
+    fn user(user_type: UserType) -> User {
+        User {
+            id: ObjectId::new(),
+            name: String::from("Test User"),
+            email: format!("{}@example.com", ObjectId::new()),
+            phone_number: String::from("+2348012345678"),
+            password: String::new(),
+            r#type: user_type.to_string(),
+            created_customers: None,
+            created_by: None,
+            spm_id: None,
+            disabled: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn grants_roles_the_manager_may_give() {
+        let admin = user(UserType::Admin);
+        let owner = user(UserType::Owner);
+
+        assert_eq!(
+            grantable_role(&admin, "owner", false).unwrap(),
+            UserType::Owner
+        );
+        assert_eq!(
+            grantable_role(&owner, "veterinarian", true).unwrap(),
+            UserType::Veterinarian
+        );
+    }
+
+    #[test]
+    fn refuses_roles_the_manager_may_not_give() {
+        let admin = user(UserType::Admin);
+        let owner = user(UserType::Owner);
+        let customer = user(UserType::Customer);
+
+        for (manager, role, staff) in [
+            (&admin, "admin", false),
+            (&admin, "super_admin", false),
+            (&owner, "owner", false),
+            (&owner, "customer", false),
+            (&customer, "worker", true),
+        ] {
+            let granted = grantable_role(manager, role, staff);
+            assert_eq!(granted.unwrap_err().status(), 403, "{role}");
+        }
+    }
+
+    #[test]
+    fn keeps_customer_and_staff_roles_apart() {
+        let admin = user(UserType::Admin);
+
+        assert_eq!(
+            grantable_role(&admin, "worker", false)
+                .unwrap_err()
+                .status(),
+            400
+        );
+        assert_eq!(
+            grantable_role(&admin, "customer", true)
+                .unwrap_err()
+                .status(),
+            400
+        );
+        assert_eq!(
+            grantable_role(&admin, "janitor", true)
+                .unwrap_err()
+                .status(),
+            400
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a MongoDB replica set at TEST_DATABASE_URL"]
+    async fn parallel_creates_stop_at_the_customer_limit() {
+        set_test_spm_secret();
+        let client = Arc::new(test_mongodb_connection().await);
+        run_migrations(&client).await.unwrap();
+        let database = client.database("fiyadb");
+
+        let admin = user(UserType::Admin);
+        database
+            .collection::<User>("users")
+            .insert_one(&admin)
+            .await
+            .unwrap();
+        let plan = Plan {
+            max_customers: 3,
+            ..Plan::default_for(admin.id)
+        };
+        PlanRepository::new(&database)
+            .upsert_plan(&plan)
+            .await
+            .unwrap();
+
+        let service = UserService::new(client.clone());
+        let results = join_all((0..10).map(|_| {
+            let payload: CreateCustomerDto = serde_json::from_value(serde_json::json!({
+                "name": "Test Customer",
+                "email": format!("{}@example.com", ObjectId::new()),
+                "phone_number": "+2348012345678",
+                "spm_id": ObjectId::new().to_hex(),
+            }))
+            .unwrap();
+            service.create_customer_user(admin.id.to_hex(), payload)
+        }))
+        .await;
+
+        let created = results.iter().filter(|result| result.is_ok()).count();
+        assert_eq!(created, plan.max_customers as usize);
+        assert!(results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .all(|err| err.status() == 409 && !is_write_conflict(err)));
+
+        let admin = UserRepository::new(&database)
+            .find_user_by_id(&admin.id.to_hex())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            admin.created_customers.unwrap_or_default().len(),
+            plan.max_customers as usize
+        );
     }
 }
@@ -1,22 +1,28 @@
 use std::{env, io::Cursor};
 
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, Nonce,
+};
 use axum_extra::headers::UserAgent;
 use chrono::{DateTime, Utc};
 use dotenvy::dotenv;
 use genpdf::{
-    elements::{self, Paragraph, StyledElement, TableLayout},
+    elements::{self, LinearLayout, PaddedElement, Paragraph, StyledElement, TableLayout},
     fonts::from_files,
+    render::Area,
     style::Style,
-    Document,
+    Context, Document, Element, Mm, Position, RenderResult, Size,
 };
 use hmac::{Hmac, Mac};
 use project_root::get_project_root;
+use qrcode::{Color, QrCode};
 use rand::{distr::Alphanumeric, Rng};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-use crate::models::spm::Cage;
+use crate::{dtos::spm_dtos::CageDto, models::spm::CageWithDeviceToken};
 
 pub fn generate_password(length: usize) -> String {
     rand::rng()
@@ -87,7 +93,101 @@ pub fn generate_secure_device_token() -> (String, String) {
     (device_token, hashed_token)
 }
 
-pub fn generate_pdf_for_cage_data(cages: Vec<Cage>) -> Result<Vec<u8>, genpdf::error::Error> {
+// Claim codes skip characters that are easy to misread on a printed label.
+const CLAIM_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+
+/// Generates a claim code formatted as `XXXX-XXXX-XXXX`.
+pub fn generate_claim_code() -> String {
+    let mut rng = rand::rng();
+    let chars: Vec<char> = (0..12)
+        .map(|_| CLAIM_CODE_ALPHABET[rng.random_range(0..CLAIM_CODE_ALPHABET.len())] as char)
+        .collect();
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Normalizes a claim code as typed by a user, so `abcd efgh-jkmn` matches
+/// `ABCD-EFGH-JKMN`.
+pub fn normalize_claim_code(claim_code: &str) -> String {
+    claim_code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Marks tokens sealed with AES-256-GCM, as opposed to the earlier keystream
+/// scheme that migration 0016 re-seals.
+pub const SEALED_TOKEN_PREFIX: &str = "v2:";
+
+const SEAL_NONCE_LEN: usize = 12;
+
+/// Encrypts a device token so the server can recover it to check signed
+/// requests. Uses AES-256-GCM under a key derived from `SPM_SECRET`, with a
+/// random nonce and the context (the cage id and token version) as associated
+/// data, so a sealed token can't be moved to another cage or version.
+pub fn seal_device_token(device_token: &str, context: &str) -> String {
+    let nonce: [u8; SEAL_NONCE_LEN] = rand::random();
+    let sealed_bytes = device_token_cipher()
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: device_token.as_bytes(),
+                aad: context.as_bytes(),
+            },
+        )
+        .expect("AES-GCM can only fail on oversized messages");
+    format!(
+        "{SEALED_TOKEN_PREFIX}{}",
+        hex::encode([nonce.as_slice(), &sealed_bytes].concat())
+    )
+}
+
+pub fn unseal_device_token(sealed_token: &str, context: &str) -> Option<String> {
+    let sealed_bytes = hex::decode(sealed_token.strip_prefix(SEALED_TOKEN_PREFIX)?).ok()?;
+    if sealed_bytes.len() < SEAL_NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed_bytes.split_at(SEAL_NONCE_LEN);
+    let device_token = device_token_cipher()
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: context.as_bytes(),
+            },
+        )
+        .ok()?;
+    String::from_utf8(device_token).ok()
+}
+
+fn device_token_cipher() -> Aes256Gcm {
+    dotenv().ok();
+    let spm_secret = env::var("SPM_SECRET").expect("SPM_SECRET must be set");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(spm_secret.as_bytes())
+        .expect("Hmac can only accept secrets of a particular length");
+    mac.update(b"device-token-seal-key");
+    <Aes256Gcm as aes_gcm::KeyInit>::new(&mac.finalize().into_bytes())
+}
+
+/// Signs `"{method}\n{path}\n{timestamp}\n{nonce}\n{body_sha256}"` with the
+/// device token, the same way devices do in signed request mode.
+pub fn sign_device_request(device_token: &str, canonical_request: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(device_token.as_bytes())
+        .expect("Hmac can only accept secrets of a particular length");
+    mac.update(canonical_request.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+pub fn generate_pdf_for_cage_data(cages: Vec<CageDto>) -> Result<Vec<u8>, genpdf::error::Error> {
     let root_path = get_project_root().expect("Failed to get project root");
     let font_path = root_path.join("fonts");
 
@@ -97,7 +197,7 @@ pub fn generate_pdf_for_cage_data(cages: Vec<Cage>) -> Result<Vec<u8>, genpdf::e
     let mut doc = Document::new(font_family);
     doc.set_title("Smart poultry monitor cage data");
 
-    let column_widths = vec![2, 3, 4, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 6, 6, 6];
+    let column_widths = vec![2, 3, 4, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 6, 6];
 
     let mut table = TableLayout::new(column_widths);
     table.set_cell_decorator(elements::FrameCellDecorator::new(true, true, false));
@@ -120,13 +220,12 @@ pub fn generate_pdf_for_cage_data(cages: Vec<Cage>) -> Result<Vec<u8>, genpdf::e
         .element(Paragraph::new("Healthy"))
         .element(Paragraph::new("Timestamp").aligned(genpdf::Alignment::Center))
         .element(Paragraph::new("Created At").aligned(genpdf::Alignment::Center))
-        .element(Paragraph::new("Updated At").aligned(genpdf::Alignment::Center))
         .push()?;
 
     cages.iter().try_for_each(|cage| {
         table
             .row()
-            .element(Paragraph::new(cage.id.to_string()))
+            .element(Paragraph::new(&cage.id))
             .element(Paragraph::new(&cage.cage_id))
             .element(Paragraph::new(&cage.assigned_monitor))
             .element(Paragraph::new(cage.livestock_no.to_string()))
@@ -145,9 +244,8 @@ pub fn generate_pdf_for_cage_data(cages: Vec<Cage>) -> Result<Vec<u8>, genpdf::e
                 cage.object_recognition.salmonella.to_string(),
             ))
             .element(Paragraph::new(cage.object_recognition.healthy.to_string()))
-            .element(Paragraph::new(cage.timestamp.to_rfc2822()))
-            .element(Paragraph::new(cage.created_at.to_rfc2822()))
-            .element(Paragraph::new(cage.updated_at.to_rfc2822()))
+            .element(Paragraph::new(&cage.timestamp))
+            .element(Paragraph::new(&cage.created_at))
             .push()
     })?;
 
@@ -156,3 +254,185 @@ pub fn generate_pdf_for_cage_data(cages: Vec<Cage>) -> Result<Vec<u8>, genpdf::e
     doc.render(&mut buffer)?;
     Ok(buffer.into_inner())
 }
+
+const PROVISIONING_LABELS_PER_ROW: usize = 3;
+
+/// Renders a printable sheet with one label per cage. Each label shows the
+/// cage ID and a QR code the device can scan for its API base URL and token.
+pub fn generate_provisioning_sheet_pdf(
+    cages: Vec<CageWithDeviceToken>,
+    api_base_url: &str,
+) -> Result<Vec<u8>, genpdf::error::Error> {
+    let root_path = get_project_root().expect("Failed to get project root");
+    let font_path = root_path.join("fonts");
+
+    let font_family =
+        from_files(font_path, "LiberationSans", None).expect("Failed to load font family");
+
+    let mut doc = Document::new(font_family);
+    doc.set_title("Smart poultry monitor provisioning sheet");
+    let mut decorator = genpdf::SimplePageDecorator::new();
+    decorator.set_margins(10);
+    doc.set_page_decorator(decorator);
+
+    let mut table = TableLayout::new(vec![1; PROVISIONING_LABELS_PER_ROW]);
+    table.set_cell_decorator(elements::FrameCellDecorator::new(true, true, false));
+
+    for row_cages in cages.chunks(PROVISIONING_LABELS_PER_ROW) {
+        let mut row = table.row();
+        for index in 0..PROVISIONING_LABELS_PER_ROW {
+            let Some(cage) = row_cages.get(index) else {
+                row = row.element(Paragraph::new(""));
+                continue;
+            };
+            let payload = serde_json::json!({
+                "api_base_url": api_base_url,
+                "cage_id": cage.cage_id,
+                "device_token": cage.device_token,
+            })
+            .to_string();
+            let qr_code = QrCode::new(payload).map_err(|err| {
+                genpdf::error::Error::new(
+                    format!("Failed to encode QR code: {err}"),
+                    genpdf::error::ErrorKind::Internal,
+                )
+            })?;
+
+            let label = LinearLayout::vertical()
+                .element(StyledElement::new(
+                    Paragraph::new(&cage.cage_id).aligned(genpdf::Alignment::Center),
+                    Style::new().bold(),
+                ))
+                .element(QrCodeElement::new(&qr_code));
+            row = row.element(PaddedElement::new(label, 3));
+        }
+        row.push()?;
+    }
+
+    doc.push(table);
+    let mut buffer = Cursor::new(Vec::new());
+    doc.render(&mut buffer)?;
+    Ok(buffer.into_inner())
+}
+
+/// Draws a QR code out of horizontal strokes, since genpdf can't fill shapes.
+struct QrCodeElement {
+    width: usize,
+    dark: Vec<bool>,
+}
+
+impl QrCodeElement {
+    // Size of one QR module and the quiet zone around the code, in modules.
+    const MODULE_MM: f32 = 0.8;
+    const QUIET_ZONE: usize = 4;
+    // genpdf strokes lines at 1pt, so each module row is covered by several
+    // strokes spaced closer than that.
+    const STROKES_PER_MODULE: usize = 3;
+
+    fn new(qr_code: &QrCode) -> Self {
+        Self {
+            width: qr_code.width(),
+            dark: qr_code
+                .to_colors()
+                .into_iter()
+                .map(|color| color == Color::Dark)
+                .collect(),
+        }
+    }
+}
+
+impl Element for QrCodeElement {
+    fn render(
+        &mut self,
+        _context: &Context,
+        area: Area<'_>,
+        style: Style,
+    ) -> Result<RenderResult, genpdf::error::Error> {
+        let side = Mm::from((self.width + 2 * Self::QUIET_ZONE) as f32 * Self::MODULE_MM);
+        if area.size().height < side {
+            return Ok(RenderResult {
+                size: Size::new(0, 0),
+                has_more: true,
+            });
+        }
+        let left = (area.size().width - side).max(Mm::from(0)) * 0.5;
+
+        for (y, row) in self.dark.chunks(self.width).enumerate() {
+            let mut x = 0;
+            while x < self.width {
+                if !row[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < self.width && row[x] {
+                    x += 1;
+                }
+
+                let x0 = left + Mm::from((start + Self::QUIET_ZONE) as f32 * Self::MODULE_MM);
+                let x1 = left + Mm::from((x + Self::QUIET_ZONE) as f32 * Self::MODULE_MM);
+                for stroke in 0..Self::STROKES_PER_MODULE {
+                    let offset = (stroke as f32 + 0.5) / Self::STROKES_PER_MODULE as f32;
+                    let y = Mm::from(((y + Self::QUIET_ZONE) as f32 + offset) * Self::MODULE_MM);
+                    area.draw_line(vec![Position::new(x0, y), Position::new(x1, y)], style);
+                }
+            }
+        }
+
+        Ok(RenderResult {
+            size: Size::new(area.size().width, side),
+            has_more: false,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Once;
+
+    use super::*;
+
+    /// Sets `SPM_SECRET` once for every test that seals tokens.
+    pub(crate) fn set_test_spm_secret() {
+        static SPM_SECRET: Once = Once::new();
+        SPM_SECRET.call_once(|| {
+            // SAFETY: runs once, before any test reads `SPM_SECRET`.
+            unsafe { env::set_var("SPM_SECRET", "test-spm-secret") };
+        });
+    }
+
+    #[test]
+    fn sealed_device_token_round_trips() {
+        set_test_spm_secret();
+        let sealed_token = seal_device_token("device-token", "cage-1:1");
+
+        assert!(sealed_token.starts_with(SEALED_TOKEN_PREFIX));
+        assert_eq!(
+            unseal_device_token(&sealed_token, "cage-1:1").as_deref(),
+            Some("device-token")
+        );
+        assert_ne!(sealed_token, seal_device_token("device-token", "cage-1:1"));
+    }
+
+    #[test]
+    fn sealed_device_token_is_bound_to_its_context() {
+        set_test_spm_secret();
+        let sealed_token = seal_device_token("device-token", "cage-1:1");
+
+        assert!(unseal_device_token(&sealed_token, "cage-1:2").is_none());
+        assert!(unseal_device_token(&sealed_token, "cage-2:1").is_none());
+    }
+
+    #[test]
+    fn tampered_sealed_device_token_is_rejected() {
+        set_test_spm_secret();
+        let sealed_token = seal_device_token("device-token", "cage-1:1");
+        let last = sealed_token.chars().last().unwrap();
+        let flipped = if last == '0' { '1' } else { '0' };
+        let tampered = format!("{}{flipped}", &sealed_token[..sealed_token.len() - 1]);
+
+        assert!(unseal_device_token(&tampered, "cage-1:1").is_none());
+        assert!(unseal_device_token(SEALED_TOKEN_PREFIX, "cage-1:1").is_none());
+        assert!(unseal_device_token("deadbeef", "cage-1:1").is_none());
+    }
+}
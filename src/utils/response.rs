@@ -148,11 +148,20 @@ impl IntoResponse for SpmDownloadCsvSuccessResponse {
 #[derive(Serialize)]
 pub struct SpmDownloadPdfSuccessResponse {
     pub data: Vec<u8>,
+    pub filename: &'static str,
 }
 
 impl SpmDownloadPdfSuccessResponse {
     pub fn new(data: Vec<u8>) -> Self {
-        Self { data }
+        Self {
+            data,
+            filename: "cage_data.pdf",
+        }
+    }
+
+    pub fn with_filename(mut self, filename: &'static str) -> Self {
+        self.filename = filename;
+        self
     }
 }
 
@@ -166,7 +175,8 @@ impl IntoResponse for SpmDownloadPdfSuccessResponse {
             )
             .header(
                 header::CONTENT_DISPOSITION,
-                HeaderValue::from_static("attachment; filename=\"cage_data.pdf\""),
+                HeaderValue::from_str(&format!("attachment; filename=\"{}\"", self.filename))
+                    .unwrap(),
             )
             .body(Body::from(self.data))
             .unwrap()
@@ -183,6 +193,14 @@ impl ApiErrorResponse {
     pub fn new(status: u16, message: String) -> Self {
         Self { status, message }
     }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl IntoResponse for ApiErrorResponse {